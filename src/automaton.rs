@@ -0,0 +1,494 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+// life-like rule: how many live neighbours bring a dead cell to life, and keep a live one alive
+#[derive(Debug, Clone)]
+pub struct Rule {
+    birth: Vec<usize>,
+    survival: Vec<usize>,
+}
+
+impl Rule {
+    pub fn new(birth: &[usize], survival: &[usize]) -> Self {
+        Rule { birth: birth.to_vec(), survival: survival.to_vec() }
+    }
+
+    pub fn alive_next(&self, alive: bool, neighbours: usize) -> bool {
+        if alive {
+            self.survival.contains(&neighbours)
+        } else {
+            self.birth.contains(&neighbours)
+        }
+    }
+}
+
+pub trait CellSet<C>: Default + Eq + Clone {
+    fn contains(&self, cell: &C) -> bool;
+    fn insert(&mut self, cell: C);
+    fn clear(&mut self);
+    fn len(&self) -> usize;
+    // equal sets have equal fingerprints
+    fn fingerprint(&self) -> u64;
+}
+
+impl<C> CellSet<C> for HashSet<C> where C: Eq + Hash + Clone {
+    fn contains(&self, cell: &C) -> bool {
+        HashSet::contains(self, cell)
+    }
+
+    fn insert(&mut self, cell: C) {
+        HashSet::insert(self, cell);
+    }
+
+    fn clear(&mut self) {
+        HashSet::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        HashSet::len(self)
+    }
+
+    fn fingerprint(&self) -> u64 {
+        // order independent, the iteration order of equal sets may differ
+        self.iter().fold(0u64, |sum, cell| {
+            let mut hasher = DefaultHasher::new();
+            cell.hash(&mut hasher);
+            sum.wrapping_add(hasher.finish())
+        })
+    }
+}
+
+// bitmap over cell indices, for topologies that number their cells
+#[derive(Debug, Clone, Default)]
+pub struct DenseSet {
+    cells: Vec<bool>,
+    len: usize,
+}
+
+impl DenseSet {
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        self.cells.iter().enumerate().filter(|(_, alive)| **alive).map(|(index, _)| index)
+    }
+}
+
+impl PartialEq for DenseSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for DenseSet {}
+
+impl CellSet<usize> for DenseSet {
+    fn contains(&self, cell: &usize) -> bool {
+        self.cells.get(*cell).copied().unwrap_or(false)
+    }
+
+    fn insert(&mut self, cell: usize) {
+        if cell >= self.cells.len() {
+            self.cells.resize(cell + 1, false);
+        }
+        if !self.cells[cell] {
+            self.cells[cell] = true;
+            self.len += 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = false);
+        self.len = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.iter().for_each(|cell| cell.hash(&mut hasher));
+        hasher.finish()
+    }
+}
+
+pub trait Topology {
+    type Cell: Copy;
+    type Set: CellSet<Self::Cell>;
+
+    fn for_each_neighbour<F>(&self, cell: Self::Cell, f: F) where F: FnMut(Self::Cell);
+
+    // every cell that needs to be looked at for the next generation
+    fn for_each_candidate<F>(&self, alive: &Self::Set, f: F) where F: FnMut(Self::Cell);
+}
+
+// candidates for unbounded topologies: live cells and their neighbours. Rules must not give birth
+// on 0 neighbours there
+fn sparse_candidates<T, F>(topology: &T, alive: &HashSet<T::Cell>, f: F)
+    where T: Topology<Set=HashSet<<T as Topology>::Cell>>, T::Cell: Eq + Hash, F: FnMut(T::Cell) {
+    let mut candidates = alive.clone();
+    for cell in alive {
+        topology.for_each_neighbour(*cell, |neighbour| { candidates.insert(neighbour); });
+    }
+    candidates.into_iter().for_each(f)
+}
+
+// generation `start` is the first one to reappear, every `period` generations. Stable states have period 1
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cycle {
+    pub start: usize,
+    pub period: usize,
+}
+
+// remembers generations to recognise the first one that repeats. Fingerprints only narrow down the
+// candidates, a cycle is reported once the states themselves are equal
+#[derive(Debug, Clone)]
+pub struct CycleDetector<S> {
+    seen: HashMap<u64, Vec<(usize, S)>>,
+}
+
+impl<S> Default for CycleDetector<S> {
+    fn default() -> Self {
+        CycleDetector { seen: HashMap::new() }
+    }
+}
+
+impl<S> CycleDetector<S> {
+    pub fn record<C>(&mut self, generation: usize, state: &S) -> Option<Cycle> where S: CellSet<C> {
+        let candidates = self.seen.entry(state.fingerprint()).or_default();
+        if let Some((start, _)) = candidates.iter().find(|(_, earlier)| earlier == state) {
+            return Some(Cycle { start: *start, period: generation - start });
+        }
+        candidates.push((generation, state.clone()));
+        None
+    }
+}
+
+pub struct Automaton<T: Topology> {
+    topology: T,
+    rule: Rule,
+    alive: T::Set,
+    next: T::Set,
+    generation: usize,
+}
+
+impl<T: Topology> Automaton<T> {
+    pub fn new<I>(topology: T, rule: Rule, alive: I) -> Self where I: IntoIterator<Item=T::Cell> {
+        let mut initial = T::Set::default();
+        for cell in alive {
+            initial.insert(cell);
+        }
+        Automaton { topology, rule, alive: initial, next: T::Set::default(), generation: 0 }
+    }
+
+    pub fn alive(&self) -> &T::Set {
+        &self.alive
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn topology(&self) -> &T {
+        &self.topology
+    }
+
+    // advances one generation, returns whether any cell changed
+    pub fn step(&mut self) -> bool {
+        let Automaton { topology, rule, alive, next, .. } = self;
+        next.clear();
+        topology.for_each_candidate(alive, |cell| {
+            let mut live_neighbours = 0;
+            topology.for_each_neighbour(cell, |neighbour| if alive.contains(&neighbour) {
+                live_neighbours += 1
+            });
+            if rule.alive_next(alive.contains(&cell), live_neighbours) {
+                next.insert(cell);
+            }
+        });
+
+        mem::swap(&mut self.alive, &mut self.next);
+        self.generation += 1;
+        self.alive != self.next
+    }
+
+    pub fn run(&mut self, generations: usize) {
+        for _ in 0..generations {
+            self.step();
+        }
+    }
+
+    // steps until a generation repeats, which also terminates for oscillating rules. Keeps every
+    // generation since the current one until then
+    pub fn run_until_cycle(&mut self) -> Cycle {
+        let mut detector = CycleDetector::default();
+        loop {
+            if let Some(cycle) = detector.record(self.generation, &self.alive) {
+                return cycle;
+            }
+            self.step();
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GridNeighbourhood {
+    Adjacent,
+    // first cell in each direction, skipping holes in the grid, optionally no further away than max_distance steps
+    Visible { max_distance: Option<i64> },
+}
+
+const GRID_DIRECTIONS: [(i64, i64); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+// finite grid of rows * cols positions, some of which may be holes that never hold a cell.
+// Cells are numbered row by row, the neighbours of every cell are looked up once on construction
+#[derive(Debug, Clone)]
+pub struct BoundedGrid {
+    rows: i64,
+    cols: i64,
+    present: Vec<bool>,
+    cells: Vec<usize>,
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl BoundedGrid {
+    pub fn new(rows: i64, cols: i64, present: Vec<bool>, neighbourhood: GridNeighbourhood) -> Self {
+        assert_eq!(present.len() as i64, rows * cols);
+        let cells = (0..present.len()).filter(|i| present[*i]).collect::<Vec<_>>();
+        let mut grid = BoundedGrid { rows, cols, present, cells, neighbours: Vec::new() };
+
+        grid.neighbours = (0..grid.present.len()).map(|index| {
+            if !grid.present[index] {
+                return Vec::new();
+            }
+            let position = grid.position(index);
+            GRID_DIRECTIONS.iter()
+                .filter_map(|direction| grid.neighbour_in_direction(position, *direction, neighbourhood))
+                .map(|neighbour| grid.index(neighbour))
+                .collect()
+        }).collect();
+
+        grid
+    }
+
+    pub fn rows(&self) -> i64 {
+        self.rows
+    }
+
+    pub fn cols(&self) -> i64 {
+        self.cols
+    }
+
+    pub fn index(&self, (row, col): (i64, i64)) -> usize {
+        (row * self.cols + col) as usize
+    }
+
+    pub fn position(&self, index: usize) -> (i64, i64) {
+        (index as i64 / self.cols, index as i64 % self.cols)
+    }
+
+    pub fn in_bounds(&self, (row, col): (i64, i64)) -> bool {
+        (0..self.rows).contains(&row) && (0..self.cols).contains(&col)
+    }
+
+    pub fn contains(&self, position: (i64, i64)) -> bool {
+        self.in_bounds(position) && self.present[self.index(position)]
+    }
+
+    // indices of all cells, holes excluded
+    pub fn cells(&self) -> &[usize] {
+        &self.cells
+    }
+
+    pub fn neighbours(&self, index: usize) -> &[usize] {
+        &self.neighbours[index]
+    }
+
+    fn neighbour_in_direction(&self, (row, col): (i64, i64), (dr, dc): (i64, i64), neighbourhood: GridNeighbourhood) -> Option<(i64, i64)> {
+        let mut current = (row + dr, col + dc);
+        match neighbourhood {
+            GridNeighbourhood::Adjacent => Some(current).filter(|c| self.contains(*c)),
+            GridNeighbourhood::Visible { max_distance } => {
+                let mut distance = 1;
+                while self.in_bounds(current) && !matches!(max_distance, Some(max) if distance > max) {
+                    if self.contains(current) {
+                        return Some(current);
+                    }
+                    current = (current.0 + dr, current.1 + dc);
+                    distance += 1;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Topology for BoundedGrid {
+    type Cell = usize;
+    type Set = DenseSet;
+
+    fn for_each_neighbour<F>(&self, cell: Self::Cell, f: F) where F: FnMut(Self::Cell) {
+        self.neighbours(cell).iter().copied().for_each(f)
+    }
+
+    fn for_each_candidate<F>(&self, _: &Self::Set, f: F) where F: FnMut(Self::Cell) {
+        self.cells.iter().copied().for_each(f)
+    }
+}
+
+// unbounded N-dimensional lattice, every cell has the 3^N - 1 surrounding cells as neighbours
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Lattice<const N: usize>;
+
+impl<const N: usize> Topology for Lattice<N> {
+    type Cell = [i64; N];
+    type Set = HashSet<[i64; N]>;
+
+    fn for_each_neighbour<F>(&self, cell: Self::Cell, mut f: F) where F: FnMut(Self::Cell) {
+        for code in 0..3usize.pow(N as u32) {
+            let mut neighbour = cell;
+            let mut rest = code;
+            for coordinate in neighbour.iter_mut() {
+                *coordinate += (rest % 3) as i64 - 1;
+                rest /= 3;
+            }
+            if neighbour != cell {
+                f(neighbour)
+            }
+        }
+    }
+
+    fn for_each_candidate<F>(&self, alive: &Self::Set, f: F) where F: FnMut(Self::Cell) {
+        sparse_candidates(self, alive, f)
+    }
+}
+
+// unbounded hexagonal tiling in cube coordinates, x + y + z is always 0
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Hex;
+
+const HEX_DIRECTIONS: [[i32; 3]; 6] = [[1, 0, -1], [1, -1, 0], [0, -1, 1], [-1, 0, 1], [-1, 1, 0], [0, 1, -1]];
+
+impl Topology for Hex {
+    type Cell = [i32; 3];
+    type Set = HashSet<[i32; 3]>;
+
+    fn for_each_neighbour<F>(&self, [x, y, z]: Self::Cell, f: F) where F: FnMut(Self::Cell) {
+        HEX_DIRECTIONS.iter().map(|[dx, dy, dz]| [x + dx, y + dy, z + dz]).for_each(f)
+    }
+
+    fn for_each_candidate<F>(&self, alive: &Self::Set, f: F) where F: FnMut(Self::Cell) {
+        sparse_candidates(self, alive, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::automaton::*;
+
+    fn neighbours<T: Topology>(topology: &T, cell: T::Cell) -> Vec<T::Cell> {
+        let mut result = Vec::new();
+        topology.for_each_neighbour(cell, |n| result.push(n));
+        result
+    }
+
+    #[test]
+    fn blinker_oscillates() {
+        let mut life = Automaton::new(Lattice::<2>, Rule::new(&[3], &[2, 3]), vec![[0, -1], [0, 0], [0, 1]]);
+        assert!(life.step());
+        let vertical = vec![[-1, 0], [0, 0], [1, 0]].into_iter().collect::<HashSet<_>>();
+        assert_eq!(life.alive(), &vertical);
+        life.step();
+        assert!(life.alive().contains(&[0, -1]));
+        assert_eq!(life.generation(), 2);
+    }
+
+    #[test]
+    fn block_is_stable() {
+        let mut life = Automaton::new(Lattice::<2>, Rule::new(&[3], &[2, 3]), vec![[0, 0], [0, 1], [1, 0], [1, 1]]);
+        // the first step already repeats the starting generation
+        assert_eq!(life.run_until_cycle(), Cycle { start: 0, period: 1 });
+        assert_eq!(life.alive().len(), 4);
+    }
+
+    #[test]
+    fn detects_oscillation() {
+        // a blinker next to a block: period 2, starting right away
+        let cells = vec![[0, -1], [0, 0], [0, 1], [5, 5], [5, 6], [6, 5], [6, 6]];
+        let mut life = Automaton::new(Lattice::<2>, Rule::new(&[3], &[2, 3]), cells);
+        assert_eq!(life.run_until_cycle(), Cycle { start: 0, period: 2 });
+        assert_eq!(life.generation(), 2);
+    }
+
+    // every set collides with every other one
+    #[derive(Debug, Clone, Default, Eq, PartialEq)]
+    struct Colliding(HashSet<i64>);
+
+    impl CellSet<i64> for Colliding {
+        fn contains(&self, cell: &i64) -> bool {
+            self.0.contains(cell)
+        }
+
+        fn insert(&mut self, cell: i64) {
+            self.0.insert(cell);
+        }
+
+        fn clear(&mut self) {
+            self.0.clear()
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn fingerprint(&self) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn fingerprint_collisions_are_no_cycles() {
+        let mut detector = CycleDetector::default();
+        let states = [vec![1], vec![2], vec![1, 2], vec![2]];
+        let found = states.iter().enumerate()
+            .map(|(generation, cells)| detector.record(generation, &Colliding(cells.iter().copied().collect())))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![None, None, None, Some(Cycle { start: 1, period: 2 })]);
+    }
+
+    #[test]
+    fn lattice_and_hex_neighbourhoods() {
+        assert_eq!(neighbours(&Lattice::<3>, [0, 0, 0]).len(), 26);
+        assert_eq!(neighbours(&Lattice::<4>, [1, 2, 3, 4]).len(), 80);
+        assert!(neighbours(&Hex, [0, 0, 0]).iter().all(|[x, y, z]| x + y + z == 0));
+    }
+
+    #[test]
+    fn bounded_grid_neighbourhoods() {
+        // #.#
+        // ...
+        // #.#
+        let present = vec![true, false, true, false, false, false, true, false, true];
+        let adjacent = BoundedGrid::new(3, 3, present.clone(), GridNeighbourhood::Adjacent);
+        assert!(adjacent.neighbours(0).is_empty());
+        let short_sighted = BoundedGrid::new(3, 3, present.clone(), GridNeighbourhood::Visible { max_distance: Some(1) });
+        assert!(short_sighted.neighbours(0).is_empty());
+        let visible = BoundedGrid::new(3, 3, present, GridNeighbourhood::Visible { max_distance: None });
+        assert_eq!(visible.neighbours(0), &[2, 6, 8]);
+        let mut candidates = Vec::new();
+        visible.for_each_candidate(&DenseSet::default(), |c| candidates.push(c));
+        assert_eq!(candidates, vec![0, 2, 6, 8]);
+    }
+
+    #[test]
+    fn dense_set_equality_ignores_capacity() {
+        let mut small = DenseSet::default();
+        let mut large = DenseSet::default();
+        large.insert(10);
+        large.clear();
+        small.insert(1);
+        large.insert(1);
+        assert_eq!(small, large);
+        assert_eq!(large.len(), 1);
+    }
+}
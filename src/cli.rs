@@ -0,0 +1,16 @@
+use std::fs;
+
+// contents of the file given on the command line, or the puzzle input without one. A file that cannot
+// be read is reported and yields None
+pub fn input_or(file: Option<&String>, default: &str) -> Option<String> {
+    match file {
+        Some(file) => match fs::read_to_string(file) {
+            Ok(contents) => Some(contents),
+            Err(e) => {
+                println!("Cannot read {}: {}", file, e);
+                None
+            }
+        },
+        None => Some(default.to_owned())
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use num::{BigInt, Integer, Signed};
+
+// x = residue (mod modulus)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Congruence {
+    pub residue: i64,
+    pub modulus: i64,
+}
+
+impl Congruence {
+    pub fn new(residue: i64, modulus: i64) -> Self {
+        Congruence { residue, modulus }
+    }
+}
+
+// all x = value (mod modulus) satisfy the system, with 0 <= value < modulus
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Solution {
+    pub value: BigInt,
+    pub modulus: BigInt,
+}
+
+impl Display for Solution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CrtError {
+    InvalidModulus { index: usize, modulus: i64 },
+    // the congruence at index contradicts the ones before it
+    Inconsistent { index: usize },
+}
+
+impl Display for CrtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CrtError::InvalidModulus { index, modulus } => write!(f, "congruence {} has non-positive modulus {}", index, modulus),
+            CrtError::Inconsistent { index } => write!(f, "congruence {} contradicts the preceding ones", index),
+        }
+    }
+}
+
+// Solves the system without requiring coprime moduli. As long as the product of all moduli fits an i64
+// every intermediate value fits an i128, otherwise the solver falls back to BigInt
+pub fn solve(congruences: &[Congruence]) -> Result<Solution, CrtError> {
+    if let Some((index, c)) = congruences.iter().enumerate().find(|(_, c)| c.modulus <= 0) {
+        return Err(CrtError::InvalidModulus { index, modulus: c.modulus });
+    }
+
+    let fits = congruences.iter().try_fold(1i64, |product, c| product.checked_mul(c.modulus)).is_some();
+    if fits {
+        let (value, modulus) = combine_all::<i128>(congruences)?;
+        Ok(Solution { value: value.into(), modulus: modulus.into() })
+    } else {
+        let (value, modulus) = combine_all::<BigInt>(congruences)?;
+        Ok(Solution { value, modulus })
+    }
+}
+
+fn combine_all<T>(congruences: &[Congruence]) -> Result<(T, T), CrtError> where T: Integer + Signed + Clone + From<i64> {
+    let mut value = T::zero();
+    let mut modulus = T::one();
+    for (index, c) in congruences.iter().enumerate() {
+        let next_modulus = T::from(c.modulus);
+        let next_value = T::from(c.residue).mod_floor(&next_modulus);
+        let combined = combine(value, modulus, next_value, next_modulus).ok_or(CrtError::Inconsistent { index })?;
+        value = combined.0;
+        modulus = combined.1;
+    }
+    Ok((value, modulus))
+}
+
+/*
+x = a1 + m1 * t must also satisfy x = a2 (mod m2), i.e. m1 * t = a2 - a1 (mod m2).
+With g = gcd(m1, m2) this is only solvable if g divides a2 - a1, and then
+t = (a2 - a1) / g * inverse(m1 / g) (mod m2 / g)
+ */
+fn combine<T>(a1: T, m1: T, a2: T, m2: T) -> Option<(T, T)> where T: Integer + Signed + Clone {
+    let gcd = m1.extended_gcd(&m2);
+    let g = gcd.gcd;
+    let difference = a2 - a1.clone();
+    if !difference.is_multiple_of(&g) {
+        return None;
+    }
+
+    let reduced = m2 / g.clone();
+    // x is the inverse of m1 / g modulo m2 / g
+    let t = (difference / g * gcd.x).mod_floor(&reduced);
+    let lcm = m1.clone() * reduced;
+    Some(((a1 + m1 * t).mod_floor(&lcm), lcm))
+}
+
+#[cfg(test)]
+mod test {
+    use num::BigInt;
+
+    use crate::crt::*;
+
+    fn solution(value: i64, modulus: i64) -> Solution {
+        Solution { value: value.into(), modulus: modulus.into() }
+    }
+
+    #[test]
+    fn coprime_moduli() {
+        let system = [Congruence::new(2, 3), Congruence::new(3, 5), Congruence::new(2, 7)];
+        assert_eq!(solve(&system), Ok(solution(23, 105)));
+        assert_eq!(solve(&[]), Ok(solution(0, 1)));
+        assert_eq!(solve(&[Congruence::new(-1, 7)]), Ok(solution(6, 7)));
+    }
+
+    #[test]
+    fn shared_factors() {
+        let system = [Congruence::new(3, 4), Congruence::new(1, 6)];
+        assert_eq!(solve(&system), Ok(solution(7, 12)));
+        let inconsistent = [Congruence::new(3, 4), Congruence::new(1, 5), Congruence::new(2, 6)];
+        assert_eq!(solve(&inconsistent), Err(CrtError::Inconsistent { index: 2 }));
+        assert_eq!(solve(&[Congruence::new(1, 0)]), Err(CrtError::InvalidModulus { index: 0, modulus: 0 }));
+    }
+
+    #[test]
+    fn large_moduli() {
+        let p = 1_000_000_007;
+        let q = 998_244_353;
+        let r = 2_147_483_647;
+        let system = [Congruence::new(1, p), Congruence::new(2, q), Congruence::new(3, r)];
+        let result = solve(&system).unwrap();
+        assert_eq!(result.modulus, BigInt::from(p) * q * r);
+        for c in &system {
+            assert_eq!(result.value.mod_floor(&c.modulus.into()), c.residue.into());
+        }
+    }
+}
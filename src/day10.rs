@@ -1,58 +1,386 @@
-use std::intrinsics::transmute;
+use std::collections::{BTreeMap, BTreeSet};
+use std::num::ParseIntError;
 use std::str::FromStr;
 
+use num::{BigUint, One, Zero};
+use num_bigint::RandBigInt;
+use rand::Rng;
+
+use crate::cli;
+
+pub const PUZZLE_GAPS: [u64; 3] = [1, 2, 3];
+
+// the sorted chain from the outlet at 0 over all adapters to the device, 3 above the highest adapter
+pub fn chain(input: &str) -> Result<Vec<u64>, ParseIntError> {
+    let mut chain = input.split_ascii_whitespace().map(u64::from_str).collect::<Result<Vec<_>, _>>()?;
+    chain.push(0);
+    chain.sort();
+    let device = chain.last().unwrap() + 3;
+    chain.push(device);
+    Ok(chain)
+}
+
 pub fn solve(input: &str) {
-    let mut input = input.split_ascii_whitespace().map(|s| u64::from_str(s).unwrap()).collect::<Vec<_>>();
-    input.push(0);
-    input.sort();
-    let last_adapter = *input.last().unwrap();
-    input.push(last_adapter + 3);
-
-    let mut differences = [0, 0, 0];
-    for i in 0..input.len() - 1 {
-        let diff = input[i + 1] - input[i];
-        if !(1..4).contains(&diff) {
-            panic!("Difference out of range: {}", diff)
+    let input = match chain(input) {
+        Ok(chain) => chain,
+        Err(e) => {
+            println!("Invalid adapter rating: {}", e);
+            return
+        }
+    };
+
+    let gaps = PUZZLE_GAPS;
+    let report = validate_chain(&input, &gaps);
+    for (difference, count) in &report.histogram {
+        println!("Difference {}: {} times", difference, count)
+    }
+    if !report.problems.is_empty() {
+        for problem in &report.problems {
+            match problem {
+                ChainProblem::Duplicate(joltage) => println!("More than one adapter rated {} jolts", joltage),
+                ChainProblem::Gap { from, to, bridges } if bridges.is_empty() =>
+                    println!("Gap from {} to {} cannot be bridged by a single adapter", from, to),
+                ChainProblem::Gap { from, to, bridges } =>
+                    println!("Gap from {} to {} can be bridged by any of {:?}", from, to, bridges),
+            }
+        }
+        return
+    }
+
+    let count = |difference| report.histogram.get(&difference).copied().unwrap_or(0);
+    println!("Part 1, 1 step * 3-step: {}", count(1) * count(3));
+
+    println!("# Paths: {}", track_arrangements(&input, &gaps));
+}
+
+// command line access to single arrangements, see usage
+pub fn query(args: &[String]) {
+    let usage = "usage: day10 [<list <limit>|nth <rank>|random> [adapter file]]";
+    if args.is_empty() {
+        solve(INPUT);
+        return
+    }
+    let (argument, file) = match args[0].as_str() {
+        "list" | "nth" if (2..=3).contains(&args.len()) => (Some(args[1].as_str()), args.get(2)),
+        "random" if args.len() <= 2 => (None, args.get(1)),
+        _ => {
+            println!("{}", usage);
+            return
+        }
+    };
+    let input = match cli::input_or(file, INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    let chain = match chain(&input) {
+        Ok(chain) => chain,
+        Err(e) => {
+            println!("Invalid adapter rating: {}", e);
+            return
+        }
+    };
+
+    let gaps = &PUZZLE_GAPS;
+    match (args[0].as_str(), argument) {
+        ("list", Some(limit)) => match usize::from_str(limit) {
+            Ok(limit) => {
+                for arrangement in Arrangements::new(&chain, gaps).take(limit) {
+                    println!("{:?}", arrangement)
+                }
+            }
+            Err(_) => println!("{}", usage)
+        },
+        ("nth", Some(rank)) => match BigUint::from_str(rank) {
+            Ok(rank) => match nth_arrangement(&chain, gaps, &rank) {
+                Some(arrangement) => println!("{:?}", arrangement),
+                None => println!("There are only {} arrangements", track_arrangements(&chain, gaps))
+            },
+            Err(_) => println!("{}", usage)
+        },
+        _ => match random_arrangement(&chain, gaps, &mut rand::thread_rng()) {
+            Some(arrangement) => println!("{:?}", arrangement),
+            None => println!("The adapters cannot be arranged")
         }
-        differences[(diff - 1) as usize] += 1;
     }
-    println!("Part 1, 1 step * 3-step: {}", differences[0] * differences[2]);
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ChainProblem {
+    // several adapters with the same rating, unless a gap of 0 is allowed
+    Duplicate(u64),
+    // bridges are the adapter joltages that would close the gap if added to the chain
+    Gap { from: u64, to: u64, bridges: Vec<u64> },
+}
 
-    let mut memo = vec![None; (last_adapter + 4) as usize];
-    println!("# Paths: {}", track_arrangements(&input.as_slice(), &mut memo[..]));
+#[derive(Debug)]
+pub struct ChainReport {
+    pub histogram: BTreeMap<u64, usize>,
+    pub problems: Vec<ChainProblem>,
 }
 
-fn can_connect(arr: &[u64], offset: usize) -> bool {
-    if offset >= arr.len() {
-        false
-    } else {
-        let j1 = arr[0];
-        let j2 = arr[offset];
-        j2 - j1 <= 3
+pub fn validate_chain(chain: &[u64], gaps: &[u64]) -> ChainReport {
+    let mut histogram = BTreeMap::new();
+    let mut problems = Vec::new();
+
+    for pair in chain.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let difference = to - from;
+        *histogram.entry(difference).or_default() += 1;
+
+        if gaps.contains(&difference) {
+            continue
+        }
+        if difference == 0 {
+            if problems.last() != Some(&ChainProblem::Duplicate(from)) {
+                problems.push(ChainProblem::Duplicate(from))
+            }
+        } else {
+            let bridges = gaps.iter()
+                .map(|gap| from + gap)
+                .filter(|bridge| *bridge < to && gaps.contains(&(to - bridge)))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            problems.push(ChainProblem::Gap { from, to, bridges })
+        }
     }
+
+    ChainReport { histogram, problems }
+}
+
+// indices of the adapters that may follow chain[from] in the sorted chain
+fn next_steps<'a>(chain: &'a [u64], gaps: &'a [u64], from: usize) -> impl Iterator<Item=usize> + 'a {
+    let max_gap = gaps.iter().copied().max().unwrap_or(0);
+    (from + 1..chain.len())
+        .take_while(move |j| chain[*j] - chain[from] <= max_gap)
+        .filter(move |j| gaps.contains(&(chain[*j] - chain[from])))
 }
 
-fn track_arrangements(input: &[u64], memo: &mut [Option<u64>]) -> u64 {
-    if input.is_empty() {
-        return 1
+// for each index, the number of ways to get from there to the last element of the sorted chain
+fn arrangement_counts(chain: &[u64], gaps: &[u64]) -> Vec<BigUint> {
+    let mut ways = vec![BigUint::zero(); chain.len()];
+    if let Some(last) = ways.last_mut() {
+        *last = BigUint::one();
     }
-    if let Some(v) = memo[input[0] as usize] {
-        return v
+
+    for i in (0..chain.len().saturating_sub(1)).rev() {
+        let mut sum = BigUint::zero();
+        for j in next_steps(chain, gaps, i) {
+            sum += &ways[j];
+        }
+        ways[i] = sum;
     }
-    let path1 = track_arrangements(&input[1..], memo);
-    let result = if can_connect(input, 2) {
-        let path2 = track_arrangements(&input[2..], memo);
-        if can_connect(input, 3) {
-            let path3 = track_arrangements(&input[3..], memo);
-            path1 + path2 + path3
-        } else {
-            path1 + path2
+
+    ways
+}
+
+// number of ways to get from the first to the last element of the sorted chain, when each step may
+// skip adapters as long as the joltage difference is one of the allowed gaps. An empty chain has none
+fn track_arrangements(chain: &[u64], gaps: &[u64]) -> BigUint {
+    arrangement_counts(chain, gaps).into_iter().next().unwrap_or_else(BigUint::zero)
+}
+
+// all arrangements of the sorted chain in lexicographic order, computed on demand
+pub struct Arrangements<'a> {
+    chain: &'a [u64],
+    gaps: &'a [u64],
+    ways: Vec<BigUint>,
+    path: Vec<usize>,
+    exhausted: bool,
+}
+
+impl<'a> Arrangements<'a> {
+    pub fn new(chain: &'a [u64], gaps: &'a [u64]) -> Self {
+        let ways = arrangement_counts(chain, gaps);
+        Arrangements { chain, gaps, ways, path: Vec::new(), exhausted: false }
+    }
+
+    // follows the smallest possible steps from the end of the path to the end of the chain
+    fn complete_path(&mut self) {
+        let mut current = *self.path.last().unwrap();
+        while current != self.chain.len() - 1 {
+            let ways = &self.ways;
+            current = next_steps(self.chain, self.gaps, current).find(|j| !ways[*j].is_zero()).unwrap();
+            self.path.push(current);
         }
-    } else {
-        path1
-    };
-    memo[input[0] as usize] = Some(result);
-    result
+    }
+
+    fn current(&self) -> Vec<u64> {
+        self.path.iter().map(|i| self.chain[*i]).collect()
+    }
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exhausted {
+            return None;
+        }
+        if self.path.is_empty() {
+            if !matches!(self.ways.first(), Some(total) if !total.is_zero()) {
+                self.exhausted = true;
+                return None;
+            }
+            self.path.push(0);
+            self.complete_path();
+            return Some(self.current());
+        }
+
+        while let Some(removed) = self.path.pop() {
+            if let Some(previous) = self.path.last() {
+                let ways = &self.ways;
+                let alternative = next_steps(self.chain, self.gaps, *previous)
+                    .find(|j| *j > removed && !ways[*j].is_zero());
+                if let Some(alternative) = alternative {
+                    self.path.push(alternative);
+                    self.complete_path();
+                    return Some(self.current());
+                }
+            }
+        }
+
+        self.exhausted = true;
+        None
+    }
+}
+
+fn unrank(chain: &[u64], gaps: &[u64], ways: &[BigUint], rank: &BigUint) -> Option<Vec<u64>> {
+    if !matches!(ways.first(), Some(total) if rank < total) {
+        return None;
+    }
+
+    let mut rank = rank.clone();
+    let mut current = 0;
+    let mut arrangement = vec![chain[0]];
+    while current != chain.len() - 1 {
+        current = next_steps(chain, gaps, current).find(|j| {
+            if rank < ways[*j] {
+                true
+            } else {
+                rank -= &ways[*j];
+                false
+            }
+        }).unwrap();
+        arrangement.push(chain[current]);
+    }
+
+    Some(arrangement)
+}
+
+// the arrangement at position rank (0 based) in lexicographic order, without enumerating the ones before
+pub fn nth_arrangement(chain: &[u64], gaps: &[u64], rank: &BigUint) -> Option<Vec<u64>> {
+    unrank(chain, gaps, &arrangement_counts(chain, gaps), rank)
+}
+
+// uniformly distributed among all arrangements of the chain
+pub fn random_arrangement<R: Rng>(chain: &[u64], gaps: &[u64], rng: &mut R) -> Option<Vec<u64>> {
+    let ways = arrangement_counts(chain, gaps);
+    let total = ways.first().filter(|total| !total.is_zero())?;
+    let rank = rng.gen_biguint_below(total);
+    unrank(chain, gaps, &ways, &rank)
+}
+
+#[cfg(test)]
+mod test {
+    use num::{BigUint, ToPrimitive};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::day10::*;
+
+    const SMALL_CHAIN: [u64; 13] = [0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22];
+
+    #[test]
+    fn enumeration_matches_count() {
+        let all = Arrangements::new(&SMALL_CHAIN, &[1, 2, 3]).collect::<Vec<_>>();
+        assert_eq!(all.len(), track_arrangements(&SMALL_CHAIN, &[1, 2, 3]).to_usize().unwrap());
+        assert_eq!(all.len(), 8);
+
+        let mut sorted = all.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, all);
+
+        let wide = Arrangements::new(&SMALL_CHAIN, &[1, 2, 3, 5]).count();
+        assert_eq!(wide, track_arrangements(&SMALL_CHAIN, &[1, 2, 3, 5]).to_usize().unwrap());
+    }
+
+    #[test]
+    fn unranking_matches_enumeration() {
+        for (rank, arrangement) in Arrangements::new(&SMALL_CHAIN, &[1, 2, 3, 5]).enumerate() {
+            assert_eq!(nth_arrangement(&SMALL_CHAIN, &[1, 2, 3, 5], &BigUint::from(rank)), Some(arrangement));
+        }
+        assert_eq!(nth_arrangement(&SMALL_CHAIN, &[1, 2, 3], &BigUint::from(8u32)), None);
+    }
+
+    #[test]
+    fn chain_report() {
+        let report = validate_chain(&SMALL_CHAIN, &[1, 2, 3]);
+        assert_eq!(report.histogram.into_iter().collect::<Vec<_>>(), vec![(1, 7), (3, 5)]);
+        assert!(report.problems.is_empty());
+
+        let report = validate_chain(&[0, 1, 5, 9], &[1, 2, 3]);
+        assert_eq!(report.problems, vec![
+            ChainProblem::Gap { from: 1, to: 5, bridges: vec![2, 3, 4] },
+            ChainProblem::Gap { from: 5, to: 9, bridges: vec![6, 7, 8] },
+        ]);
+
+        let report = validate_chain(&[0, 1, 8], &[1, 2, 3]);
+        assert_eq!(report.problems, vec![ChainProblem::Gap { from: 1, to: 8, bridges: vec![] }]);
+    }
+
+    #[test]
+    fn duplicate_adapters() {
+        let report = validate_chain(&[0, 1, 4, 4, 4, 5], &[1, 2, 3]);
+        assert_eq!(report.histogram[&0], 2);
+        assert_eq!(report.problems, vec![ChainProblem::Duplicate(4)]);
+        assert!(validate_chain(&[0, 1, 1, 2], &[0, 1]).problems.is_empty());
+    }
+
+    #[test]
+    fn unreachable_end() {
+        assert_eq!(Arrangements::new(&[0, 1, 5], &[1, 2, 3]).next(), None);
+        assert_eq!(track_arrangements(&[0, 1, 5], &[1, 2, 3]), BigUint::from(0u32));
+        assert_eq!(random_arrangement(&[0, 1, 5], &[1, 2, 3], &mut rand::thread_rng()), None);
+
+        assert_eq!(Arrangements::new(&[], &[1, 2, 3]).count(), 0);
+        assert_eq!(track_arrangements(&[], &[1, 2, 3]), BigUint::from(0u32));
+        assert_eq!(nth_arrangement(&[], &[1, 2, 3], &BigUint::from(0u32)), None);
+    }
+
+    #[test]
+    fn random_arrangements_are_valid() {
+        let mut rng = StdRng::seed_from_u64(2020);
+        let all = Arrangements::new(&SMALL_CHAIN, &[1, 2, 3]).collect::<Vec<_>>();
+        let mut sampled = Vec::new();
+        for _ in 0..200 {
+            let arrangement = random_arrangement(&SMALL_CHAIN, &[1, 2, 3], &mut rng).unwrap();
+            assert!(all.contains(&arrangement));
+            assert!(validate_chain(&arrangement, &[1, 2, 3]).problems.is_empty());
+            sampled.push(arrangement);
+        }
+        sampled.sort();
+        sampled.dedup();
+        // with 200 draws, each of the 8 arrangements turns up
+        assert_eq!(sampled, all);
+    }
+
+    #[test]
+    fn counts_beyond_u64() {
+        // every adapter one step apart, the counts follow the tribonacci numbers
+        let chain = (0..=100).collect::<Vec<u64>>();
+        let mut ways = [1u128, 0, 0];
+        for _ in 0..100 {
+            ways = [ways[0] + ways[1] + ways[2], ways[0], ways[1]];
+        }
+        assert!(ways[0] > u64::MAX as u128);
+        assert_eq!(track_arrangements(&chain, &[1, 2, 3]), BigUint::from(ways[0]));
+
+        let last = BigUint::from(ways[0] - 1);
+        let steps = (0..=100).step_by(3).chain(Some(100)).collect::<Vec<_>>();
+        assert_eq!(nth_arrangement(&chain, &[1, 2, 3], &last), Some(steps));
+    }
 }
 
 pub const INPUT: &str = "149
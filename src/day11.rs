@@ -1,79 +1,246 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::automaton::{Automaton, BoundedGrid, CellSet, Cycle, CycleDetector, DenseSet, GridNeighbourhood, Rule};
+
 const FLOOR: u8 = b'.';
 const EMPTY: u8 = b'L';
 const OCCUPIED: u8 = b'#';
 
-fn step(target: &mut [u8], source: &[u8], rows: i64, cols: i64) -> bool {
-    let mut modified = false;
-    let mut write = |r: i64, c: i64, val: u8|{
-        modified = true;
-        target[(r * (1 + cols) + c) as usize] = val
-    };
-    let read = |r: i64, c: i64| {
-        if (0i64..rows).contains(&r) && (0i64..cols).contains(&c){
-            source[(r * (1 + cols) + c) as usize]
-        } else {
-            FLOOR
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LayoutError {
+    RaggedRow { row: usize, len: usize, expected: usize },
+    UnknownTile { row: usize, col: usize, tile: char },
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::RaggedRow { row, len, expected } => write!(f, "row {} has {} positions instead of {}", row, len, expected),
+            LayoutError::UnknownTile { row, col, tile } => write!(f, "row {}, column {}: unknown tile {:?}", row, col, tile),
+        }
+    }
+}
+
+// rows and columns in errors count from 1
+fn parse(input: &str, neighbourhood: GridNeighbourhood) -> Result<(BoundedGrid, Vec<usize>), LayoutError> {
+    let lines = input.lines().collect::<Vec<_>>();
+    let rows = lines.len();
+    let cols = lines.first().map_or(0, |l| l.chars().count());
+    let mut present = Vec::with_capacity(rows * cols);
+    let mut occupied = Vec::new();
+
+    for (row, line) in lines.iter().enumerate() {
+        let len = line.chars().count();
+        if len != cols {
+            return Err(LayoutError::RaggedRow { row: row + 1, len, expected: cols });
         }
-    };
-    let read_closest_non_floor = |mut r: i64, mut c: i64, dr: i64, dc: i64| {
-        loop {
-            r += dr;
-            c += dc;
-            if !(0i64..rows).contains(&r) || !(0i64..cols).contains(&c) {
-                return None
+        for (col, tile) in line.chars().enumerate() {
+            let byte = if tile.is_ascii() { tile as u8 } else { 0 };
+            match byte {
+                OCCUPIED => occupied.push(present.len()),
+                EMPTY | FLOOR => (),
+                _ => return Err(LayoutError::UnknownTile { row: row + 1, col: col + 1, tile })
             }
-            let current = read(r, c);
-            if current != FLOOR {
-                return Some(current)
+            present.push(byte != FLOOR);
+        }
+    }
+
+    Ok((BoundedGrid::new(rows as i64, cols as i64, present, neighbourhood), occupied))
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SeatingRules {
+    pub neighbourhood: GridNeighbourhood,
+    // occupied seats are abandoned once this many neighbours are occupied
+    pub abandon_threshold: usize,
+}
+
+impl SeatingRules {
+    fn rule(&self) -> Rule {
+        // seats fill up when no neighbour is occupied
+        Rule::new(&[0], &(0..self.abandon_threshold).collect::<Vec<_>>())
+    }
+}
+
+pub const PUZZLE_RULES: [SeatingRules; 2] = [
+    SeatingRules { neighbourhood: GridNeighbourhood::Adjacent, abandon_threshold: 4 },
+    SeatingRules { neighbourhood: GridNeighbourhood::Visible { max_distance: None }, abandon_threshold: 5 },
+];
+
+#[derive(Debug, Copy, Clone)]
+pub struct Outcome {
+    // occupied seats in the first repeated generation
+    pub occupied: usize,
+    pub cycle: Cycle,
+}
+
+// runs until the layout stops changing, or starts repeating itself
+pub fn simulate(input: &str, rules: SeatingRules) -> Result<Outcome, LayoutError> {
+    let (grid, occupied) = parse(input, rules.neighbourhood)?;
+    let mut seating = Automaton::new(grid, rules.rule(), occupied);
+    let cycle = seating.run_until_cycle();
+    Ok(Outcome { occupied: seating.alive().len(), cycle })
+}
+
+fn render(grid: &BoundedGrid, occupied: &DenseSet, colours: bool) -> String {
+    let mut frame = String::with_capacity(((grid.cols() + 1) * grid.rows()) as usize);
+    for row in 0..grid.rows() {
+        for col in 0..grid.cols() {
+            let index = grid.index((row, col));
+            let symbol = if !grid.contains((row, col)) {
+                FLOOR
+            } else if occupied.contains(&index) {
+                OCCUPIED
+            } else {
+                EMPTY
+            };
+            if colours {
+                frame.push_str(match symbol {
+                    OCCUPIED => "\x1b[31m",
+                    EMPTY => "\x1b[32m",
+                    _ => "\x1b[90m",
+                })
             }
+            frame.push(symbol as char);
+        }
+        if colours {
+            frame.push_str("\x1b[0m")
         }
-    };
-    let occupied_count = |r, c| {
-        let arr = [
-            read_closest_non_floor(r, c, -1, -1),
-            read_closest_non_floor(r, c, -1, 0),
-            read_closest_non_floor(r, c, -1, 1),
-            read_closest_non_floor(r, c, 0, -1),
-            read_closest_non_floor(r, c, 0, 1),
-            read_closest_non_floor(r, c, 1, -1),
-            read_closest_non_floor(r, c, 1, 0),
-            read_closest_non_floor(r, c, 1, 1),
-        ];
-        (&arr[..]).iter().filter(|n| **n == Some(OCCUPIED)).count()
-    };
-
-    for row in 0..rows {
-        for col in 0..cols {
-            match read(row, col) {
-                EMPTY if occupied_count(row, col) == 0 =>  {
-                    write(row, col, OCCUPIED)
-                },
-                OCCUPIED if occupied_count(row, col) >= 5 => {
-                    write(row, col, EMPTY)
-                }
-                _ => (),
+        frame.push('\n');
+    }
+    frame
+}
+
+// shows every generation in the terminal until the layout settles or repeats, optionally writing each
+// one to frames_dir as plain text
+pub fn play(input: &str, rules: SeatingRules, delay: Duration, frames_dir: Option<&Path>) -> io::Result<Outcome> {
+    let (grid, occupied) = parse(input, rules.neighbourhood)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    let mut seating = Automaton::new(grid, rules.rule(), occupied);
+    if let Some(dir) = frames_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut detector = CycleDetector::default();
+    detector.record(0, seating.alive());
+    let mut changed = 0;
+    loop {
+        let grid = seating.topology();
+        let generation = seating.generation();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        write!(out, "\x1b[2J\x1b[H{}", render(grid, seating.alive(), true))?;
+        writeln!(out, "Generation {}: {} seats changed, {} occupied", generation, changed, seating.alive().len())?;
+        out.flush()?;
+        if let Some(dir) = frames_dir {
+            fs::write(dir.join(format!("frame_{:04}.txt", generation)), render(grid, seating.alive(), false))?;
+        }
+
+        let previous = seating.alive().clone();
+        seating.step();
+        if let Some(cycle) = detector.record(seating.generation(), seating.alive()) {
+            return Ok(Outcome { occupied: seating.alive().len(), cycle });
+        }
+        changed = seating.topology().cells().iter()
+            .filter(|cell| previous.contains(cell) != seating.alive().contains(cell))
+            .count();
+        thread::sleep(delay);
+    }
+}
+
+pub fn solve(input: &str, rules: &[SeatingRules]) {
+    for rules in rules {
+        match simulate(input, *rules) {
+            Ok(Outcome { occupied, cycle: Cycle { start, period: 1 } }) =>
+                println!("{:?}: stable after {} generations, {} occupied", rules, start, occupied),
+            Ok(Outcome { occupied, cycle: Cycle { start, period } }) =>
+                println!("{:?}: oscillates every {} generations from generation {} on, {} occupied", rules, period, start, occupied),
+            Err(e) => {
+                println!("Invalid layout, {}", e);
+                return
             }
         }
     }
+}
 
-    modified
+// without arguments both parts are solved, play animates one of them
+pub fn query(args: &[String]) {
+    let usage = "usage: day11 [play <1|2> [delay in ms] [frames dir]]";
+    if args.is_empty() {
+        solve(INPUT, &PUZZLE_RULES);
+        return
+    }
+    let part = args.get(1).and_then(|p| usize::from_str(p).ok()).filter(|p| (1..=2).contains(p));
+    let delay = args.get(2).map_or(Ok(100), |d| u64::from_str(d));
+    match (args[0].as_str(), part, delay) {
+        ("play", Some(part), Ok(delay)) if args.len() <= 4 => {
+            let frames_dir = args.get(3).map(Path::new);
+            match play(INPUT, PUZZLE_RULES[part - 1], Duration::from_millis(delay), frames_dir) {
+                Ok(Outcome { occupied, cycle }) => println!("Settled into {:?} with {} occupied", cycle, occupied),
+                Err(e) => println!("Playback failed: {}", e)
+            }
+        }
+        _ => println!("{}", usage)
+    }
 }
 
-pub fn solve(input: &str) {
-    let mut current = input.as_bytes().to_vec();
-    let row_size = current.iter().position(|b| *b == b'\n').unwrap_or(current.len()) as i64;
-    let rows = current.iter().filter(|b| **b == b'\n').count() as i64;
-    let mut next = current.clone();
+#[cfg(test)]
+mod test {
+    use crate::day11::*;
 
-    while step(&mut next, &current, rows, row_size) {
-        current.copy_from_slice(&next)
+    #[test]
+    fn puzzle_rules() {
+        assert_eq!(simulate(EXAMPLE_INPUT, PUZZLE_RULES[0]).unwrap().occupied, 37);
+        assert_eq!(simulate(EXAMPLE_INPUT, PUZZLE_RULES[1]).unwrap().occupied, 26);
     }
 
-    println!("{}", current.iter().filter(|byte|**byte == OCCUPIED).count());
+    #[test]
+    fn custom_rules() {
+        // seats that are never abandoned all fill up in the first generation
+        let seats = EXAMPLE_INPUT.chars().filter(|c| *c == 'L').count();
+        let outcome = simulate(EXAMPLE_INPUT, SeatingRules { neighbourhood: GridNeighbourhood::Adjacent, abandon_threshold: 9 }).unwrap();
+        assert_eq!(outcome.occupied, seats);
+        assert_eq!(outcome.cycle, Cycle { start: 1, period: 1 });
+
+        // looking a single step is the same as looking at adjacent seats
+        let short_sight = SeatingRules { neighbourhood: GridNeighbourhood::Visible { max_distance: Some(1) }, abandon_threshold: 4 };
+        assert_eq!(simulate(EXAMPLE_INPUT, short_sight).unwrap().occupied, 37);
+        let far_sight = SeatingRules { neighbourhood: GridNeighbourhood::Visible { max_distance: Some(10) }, abandon_threshold: 5 };
+        assert_eq!(simulate(EXAMPLE_INPUT, far_sight).unwrap().occupied, 26);
+        let near_sight = SeatingRules { neighbourhood: GridNeighbourhood::Visible { max_distance: Some(2) }, abandon_threshold: 5 };
+        assert_ne!(simulate(EXAMPLE_INPUT, near_sight).unwrap().occupied, 26);
+    }
+
+    #[test]
+    fn layout_errors() {
+        let rules = PUZZLE_RULES[0];
+        assert_eq!(simulate("L.L\nLL\n", rules).unwrap_err(), LayoutError::RaggedRow { row: 2, len: 2, expected: 3 });
+        assert_eq!(simulate("L.L\nLL# \n", rules).unwrap_err(), LayoutError::RaggedRow { row: 2, len: 4, expected: 3 });
+        assert_eq!(simulate("L.L\nLxL\n", rules).unwrap_err(), LayoutError::UnknownTile { row: 2, col: 2, tile: 'x' });
+        assert_eq!(simulate("L.Ŀ", rules).unwrap_err(), LayoutError::UnknownTile { row: 1, col: 3, tile: 'Ŀ' });
+        assert_eq!(simulate("", rules).unwrap().occupied, 0);
+    }
 }
 
-// a much better solution would build a map to "reference indices" for each position and direction,
-// but my brain is mush right now
+#[cfg(test)]
+pub const EXAMPLE_INPUT: &str = "L.LL.LL.LL
+LLLLLLL.LL
+L.L.L..L..
+LLLL.LL.LL
+L.LL.LL.LL
+L.LLLLL.LL
+..L.L.....
+LLLLLLLLLL
+L.LLLLLL.L
+L.LLLLL.LL";
 
 pub const INPUT: &str = "LLLLLLLLLL.LLLL.LLLLLLL.LLLLLLL..LLLLLLLLLLLLLLL.LLLLLLLLLLLLLLLLLLL.LLLLLLLLLLLLLL.LL.LLLLLLL
 LLLL.LLLLL.LLLLLLLLLLLL.LLLLLLLLLLLLLLLL.LLLLLLL.LLLLLLLLLLL.LLLLLLLLLLLLLLLLL.LLLL.LLLLLLLLLL
@@ -1,57 +1,86 @@
-use regex::Regex;
-use std::ops::{Add, Sub, Mul};
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::iter;
 use std::str::FromStr;
 
-#[derive(Copy, Clone, Debug)]
-struct Vector2D(i64, i64);
+use crate::cli;
+use crate::geometry::{BoundingBox, Vector2D, Vector2F};
 
 const EAST: Vector2D = Vector2D(1, 0);
 const WEST: Vector2D = Vector2D(-1, 0);
 const NORTH: Vector2D = Vector2D(0, 1);
 const SOUTH: Vector2D = Vector2D(0, -1);
 
-impl Add for Vector2D {
-    type Output = Vector2D;
+#[derive(Copy, Clone, Debug)]
+pub enum Operation {
+    East,
+    South,
+    West,
+    North,
+    Forward,
+    Right,
+    Left
+}
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Vector2D(self.0 + rhs.0, self.1 + rhs.1)
+impl Operation {
+    fn compass_direction(self) -> Option<Vector2D> {
+        match self {
+            Operation::East => Some(EAST),
+            Operation::West => Some(WEST),
+            Operation::North => Some(NORTH),
+            Operation::South => Some(SOUTH),
+            _ => None
+        }
     }
 }
 
-impl Mul<i64> for Vector2D {
-    type Output = Vector2D;
+#[derive(Debug)]
+pub struct Move(pub Operation, pub i64);
 
-    fn mul(self, rhs: i64) -> Self::Output {
-        Vector2D(self.0 * rhs, self.1 * rhs)
-    }
+// a point in the space a model navigates in, measured from the starting point
+pub trait Position: Copy + Debug + PartialEq {
+    fn manhattan(self) -> f64;
+
+    // projection onto the sea surface, for charts
+    fn plan(self) -> Vector2F;
 }
 
-impl Vector2D {
-    fn rotate_steps(self, steps: i64) -> Self {
-        match steps % 4 {
-            0 => self,
-            1 | -3 => Vector2D(self.1, -self.0),
-            2 | -2 => Vector2D(-self.0, -self.1),
-            3 | -1 => Vector2D(-self.1, self.0),
-            _ => unreachable!()
-        }
+impl Position for Vector2F {
+    fn manhattan(self) -> f64 {
+        Vector2F::manhattan(self)
+    }
+
+    fn plan(self) -> Vector2F {
+        self
     }
 }
 
+// one interpretation of the navigation instructions. Models choose their own space, e.g. to add altitude
+pub trait NavigationModel: Debug {
+    type Position: Position;
 
-#[derive(Copy, Clone, Debug)]
-enum Operation {
-    East,
-    South,
-    West,
-    North,
-    Forward,
-    Right,
-    Left
-}
+    fn apply(&mut self, movement: &Move);
 
-#[derive(Debug)]
-struct Move(Operation, i64);
+    fn position(&self) -> Self::Position;
+
+    // direction the ship moves in when going forward, relative to its position
+    fn heading(&self) -> Self::Position;
+
+    // absolute positions of the waypoints steered by, if any
+    fn waypoints(&self) -> Vec<Self::Position> {
+        Vec::new()
+    }
+
+    fn distance(&self) -> f64 {
+        self.position().manhattan()
+    }
+
+    fn turns(&self) -> Turns {
+        Turns::RightAngles
+    }
+}
 
 #[derive(Clone, Debug)]
 struct Ship {
@@ -67,57 +96,591 @@ impl Ship {
         }
     }
 
-    fn manhattan_distance(&self) -> i64 {
-        self.position.0.abs() + self.position.1.abs()
+    // moving forward and turning mean the same thing in every model. Compass moves are returned as
+    // an offset for the model to apply
+    fn steer(&mut self, movement: &Move) -> Option<Vector2D> {
+        match movement {
+            Move(Operation::Forward, x) => self.position += self.heading * *x,
+            Move(Operation::Right, x) => self.heading = self.heading.rotate_steps(x / 90),
+            Move(Operation::Left, x) => self.heading = self.heading.rotate_steps(-x / 90),
+            Move(op, x) => return op.compass_direction().map(|direction| direction * *x),
+        }
+        None
     }
+}
 
-    fn apply_default(mut self, movement: &Move) -> Self {
-        match movement {
-            Move(Operation::Forward, x) => Ship { position: self.position + self.heading * *x, ..self },
-            Move(Operation::Right, x) => Ship { heading: self.heading.rotate_steps(x / 90), ..self},
-            Move(Operation::Left, x) => Ship { heading: self.heading.rotate_steps(-x / 90), ..self},
-            Move(Operation::East, x) => Ship { position: self.position + EAST * *x, ..self },
-            Move(Operation::West, x) => Ship { position: self.position + WEST * *x, ..self },
-            Move(Operation::South, x) =>  Ship { position: self.position + SOUTH * *x, ..self},
-            Move(Operation::North, x) =>  Ship { position: self.position + NORTH * *x, ..self},
+// compass moves shift the ship
+#[derive(Clone, Debug)]
+pub struct HeadingModel(Ship);
+
+impl NavigationModel for HeadingModel {
+    type Position = Vector2F;
+
+    fn apply(&mut self, movement: &Move) {
+        if let Some(offset) = self.0.steer(movement) {
+            self.0.position += offset
         }
     }
-    fn apply_twist(mut self, movement: &Move) -> Self {
-        match movement {
-            Move(Operation::Forward, x) => Ship { position: self.position + self.heading * *x, ..self },
-            Move(Operation::Right, x) => Ship { heading: self.heading.rotate_steps(x / 90), ..self},
-            Move(Operation::Left, x) => Ship { heading: self.heading.rotate_steps(-x / 90), ..self},
-            Move(Operation::East, x) => Ship { heading: self.heading + EAST * *x, ..self },
-            Move(Operation::West, x) => Ship { heading: self.heading + WEST * *x, ..self },
-            Move(Operation::South, x) =>  Ship { heading: self.heading + SOUTH * *x, ..self},
-            Move(Operation::North, x) =>  Ship { heading: self.heading + NORTH * *x, ..self},
+
+    fn position(&self) -> Vector2F {
+        self.0.position.to_f64()
+    }
+
+    fn heading(&self) -> Vector2F {
+        self.0.heading.to_f64()
+    }
+}
+
+// compass moves shift the waypoint, which moves along with the ship
+#[derive(Clone, Debug)]
+pub struct WaypointModel(Ship);
+
+impl NavigationModel for WaypointModel {
+    type Position = Vector2F;
+
+    fn apply(&mut self, movement: &Move) {
+        if let Some(offset) = self.0.steer(movement) {
+            self.0.heading += offset
         }
     }
+
+    fn position(&self) -> Vector2F {
+        self.0.position.to_f64()
+    }
+
+    fn heading(&self) -> Vector2F {
+        self.0.heading.to_f64()
+    }
+
+    fn waypoints(&self) -> Vec<Vector2F> {
+        vec![(self.0.position + self.0.heading).to_f64()]
+    }
+}
+
+// floating point variant of either model above, turning by any angle
+#[derive(Clone, Debug)]
+pub struct ContinuousModel {
+    heading: Vector2F,
+    position: Vector2F,
+    waypoint: bool,
 }
 
-pub fn solve(input: &str) {
-    let parse_re = Regex::new("([NSWEFRL])(\\d+)").unwrap();
-    let moves = parse_re.captures_iter(input).map(|cap| {
-        let op = match cap.get(1).unwrap().as_str() {
-            "R" => Operation::Right,
-            "L" => Operation::Left,
-            "F" => Operation::Forward,
-            "N" => Operation::North,
-            "S" => Operation::South,
-            "W" => Operation::West,
-            "E" => Operation::East,
-            _ => unreachable!()
+impl ContinuousModel {
+    fn shift(&mut self, direction: Vector2D, x: f64) {
+        let offset = direction.to_f64() * x;
+        if self.waypoint {
+            self.heading = self.heading + offset
+        } else {
+            self.position = self.position + offset
+        }
+    }
+}
+
+impl NavigationModel for ContinuousModel {
+    type Position = Vector2F;
+
+    fn apply(&mut self, movement: &Move) {
+        let Move(op, x) = *movement;
+        let x = x as f64;
+        match op {
+            Operation::Forward => self.position = self.position + self.heading * x,
+            Operation::Right => self.heading = self.heading.rotate_degrees(x),
+            Operation::Left => self.heading = self.heading.rotate_degrees(-x),
+            Operation::East => self.shift(EAST, x),
+            Operation::South => self.shift(SOUTH, x),
+            Operation::West => self.shift(WEST, x),
+            Operation::North => self.shift(NORTH, x),
+        }
+    }
+
+    fn position(&self) -> Vector2F {
+        self.position
+    }
+
+    fn heading(&self) -> Vector2F {
+        self.heading
+    }
+
+    fn waypoints(&self) -> Vec<Vector2F> {
+        if self.waypoint {
+            vec![self.position + self.heading]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn turns(&self) -> Turns {
+        Turns::Continuous
+    }
+}
+
+pub const PUZZLE_MODELS: [&str; 2] = ["heading", "waypoint"];
+
+// a model as seen from the command line, whatever space it navigates in
+pub trait Navigator: Debug {
+    fn accepted_turns(&self) -> Turns;
+
+    // applies the moves, returning the initial state and the state after every move, charted on the sea surface
+    fn sail(&mut self, moves: &[Move]) -> Vec<Snapshot<Vector2F>>;
+
+    fn distance_travelled(&self) -> f64;
+}
+
+impl<M: NavigationModel> Navigator for M {
+    fn accepted_turns(&self) -> Turns {
+        self.turns()
+    }
+
+    fn sail(&mut self, moves: &[Move]) -> Vec<Snapshot<Vector2F>> {
+        trajectory(moves, self).into_iter().map(Snapshot::plan).collect()
+    }
+
+    fn distance_travelled(&self) -> f64 {
+        self.distance()
+    }
+}
+
+pub type NavigatorFactory = fn() -> Box<dyn Navigator>;
+
+// the models selectable by name. Each is created fresh for every run
+pub struct Registry {
+    models: Vec<(&'static str, NavigatorFactory)>
+}
+
+impl Registry {
+    pub fn register(&mut self, name: &'static str, create: NavigatorFactory) {
+        self.models.retain(|&(known, _)| known != name);
+        self.models.push((name, create))
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn Navigator>> {
+        self.models.iter().find(|&&(known, _)| known == name).map(|(_, create)| create())
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.models.iter().map(|&(name, _)| name).collect()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry { models: Vec::new() };
+        registry.register("heading", || Box::new(HeadingModel(Ship::new(EAST))));
+        registry.register("waypoint", || Box::new(WaypointModel(Ship::new(Vector2D(10, 1)))));
+        registry.register("continuous-heading", || Box::new(ContinuousModel {
+            heading: EAST.to_f64(), position: Vector2F(0.0, 0.0), waypoint: false
+        }));
+        registry.register("continuous-waypoint", || Box::new(ContinuousModel {
+            heading: Vector2D(10, 1).to_f64(), position: Vector2F(0.0, 0.0), waypoint: true
+        }));
+        registry
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Turns {
+    // only multiples of 90 degrees are accepted
+    RightAngles,
+    // any angle, for ships with a floating point heading
+    Continuous,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum InstructionError {
+    UnknownAction { line: usize, action: char },
+    InvalidValue { line: usize, value: String },
+    InvalidTurn { line: usize, degrees: i64 },
+}
+
+impl Display for InstructionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InstructionError::UnknownAction { line, action } => write!(f, "line {}: unknown action '{}'", line, action),
+            InstructionError::InvalidValue { line, value } => write!(f, "line {}: '{}' is not a non-negative integer", line, value),
+            InstructionError::InvalidTurn { line, degrees } => write!(f, "line {}: turn by {} degrees is not a multiple of 90", line, degrees),
+        }
+    }
+}
+
+// collects a problem for every bad line. Which turns are valid depends on the model
+fn parse(input: &str, turns: Turns) -> Result<Vec<Move>, Vec<InstructionError>> {
+    let mut moves = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, text) in input.lines().enumerate() {
+        let line = index + 1;
+        let text = text.trim();
+        let action = match text.chars().next() {
+            Some(action) => action,
+            None => continue,
+        };
+        let value = &text[action.len_utf8()..];
+
+        let op = match action {
+            'R' => Operation::Right,
+            'L' => Operation::Left,
+            'F' => Operation::Forward,
+            'N' => Operation::North,
+            'S' => Operation::South,
+            'W' => Operation::West,
+            'E' => Operation::East,
+            _ => {
+                errors.push(InstructionError::UnknownAction { line, action });
+                continue
+            }
+        };
+        let digits_only = value.bytes().all(|b| b.is_ascii_digit());
+        let arg = match i64::from_str(value).ok().filter(|_| digits_only) {
+            Some(arg) => arg,
+            None => {
+                errors.push(InstructionError::InvalidValue { line, value: value.to_owned() });
+                continue
+            }
         };
-        let arg = i64::from_str(cap.get(2).unwrap().as_str()).unwrap();
-        Move(op, arg)
-    }).collect::<Vec<_>>();
-
-    let result_simple = moves.iter().fold(Ship::new(EAST), Ship::apply_default);
-    println!("DEFAULT: final state: {:?}, distance is {}", &result_simple, result_simple.manhattan_distance());
-    let result_twist = moves.iter().fold(Ship::new(Vector2D(10, 1)), Ship::apply_twist);
-    println!("TWIST: final state: {:?}, distance is {}", &result_twist, result_twist.manhattan_distance())
+        if matches!(op, Operation::Left | Operation::Right) && turns == Turns::RightAngles && arg % 90 != 0 {
+            errors.push(InstructionError::InvalidTurn { line, degrees: arg });
+            continue
+        }
+
+        moves.push(Move(op, arg))
+    }
+
+    if errors.is_empty() {
+        Ok(moves)
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<P> {
+    position: P,
+    heading: P,
+    waypoints: Vec<P>,
+}
+
+impl<P: Position> Snapshot<P> {
+    fn of<M: NavigationModel<Position=P> + ?Sized>(model: &M) -> Self {
+        Snapshot { position: model.position(), heading: model.heading(), waypoints: model.waypoints() }
+    }
+
+    fn plan(self) -> Snapshot<Vector2F> {
+        Snapshot {
+            position: self.position.plan(),
+            heading: self.heading.plan(),
+            waypoints: self.waypoints.into_iter().map(Position::plan).collect(),
+        }
+    }
+}
+
+// the initial state, followed by the state after every move
+fn trajectory<M: NavigationModel + ?Sized>(moves: &[Move], model: &mut M) -> Vec<Snapshot<M::Position>> {
+    let mut states = Vec::with_capacity(moves.len() + 1);
+    states.push(Snapshot::of(model));
+    for movement in moves {
+        model.apply(movement);
+        states.push(Snapshot::of(model));
+    }
+    states
+}
+
+// draws the route of the ship under the named model, with markers at every stop and at the waypoints
+pub fn export_svg<W: Write>(input: &str, registry: &Registry, model_name: &str, out: &mut W) -> io::Result<()> {
+    let mut navigation = registry.create(model_name).ok_or_else(|| {
+        io::Error::new(ErrorKind::InvalidInput, format!("unknown navigation model {}, known are {:?}", model_name, registry.names()))
+    })?;
+    let moves = parse(input, navigation.accepted_turns()).map_err(|errors| {
+        let messages = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        io::Error::new(ErrorKind::InvalidData, messages.join(", "))
+    })?;
+    let states = navigation.sail(&moves);
+    // svg y axis points south. Subtracting avoids printing -0
+    let screen = |v: Vector2F| Vector2F(v.0, 0.0 - v.1);
+
+    let points = states.iter().flat_map(|state| iter::once(&state.position).chain(&state.waypoints));
+    let bounds = BoundingBox::from_points(points.map(|p| screen(*p).round().into())).unwrap();
+    let [width, height] = bounds.extent();
+    let padding = width.max(height) / 10 + 1;
+    let stroke = (width.max(height) as f64 / 500.0).max(0.5);
+
+    writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+             bounds.min[0] - padding, bounds.min[1] - padding, width + 2 * padding, height + 2 * padding)?;
+    write!(out, r#"  <path fill="none" stroke="black" stroke-width="{}" d=""#, stroke)?;
+    for (i, state) in states.iter().enumerate() {
+        let Vector2F(x, y) = screen(state.position);
+        write!(out, "{}{} {} ", if i == 0 { "M" } else { "L" }, x, y)?;
+    }
+    writeln!(out, r#""/>"#)?;
+
+    for state in &states {
+        let Vector2F(x, y) = screen(state.position);
+        writeln!(out, r#"  <circle cx="{}" cy="{}" r="{}" fill="black"/>"#, x, y, 1.5 * stroke)?;
+        for waypoint in &state.waypoints {
+            let Vector2F(wx, wy) = screen(*waypoint);
+            writeln!(out, r#"  <circle cx="{}" cy="{}" r="{}" fill="steelblue"/>"#, wx, wy, 1.5 * stroke)?;
+        }
+    }
+
+    let font = 10.0 * stroke;
+    let start = states.first().unwrap().position;
+    let end = states.last().unwrap().position;
+    let Vector2F(sx, sy) = screen(start);
+    writeln!(out, r#"  <text x="{}" y="{}" font-size="{}" fill="green">start ({}, {})</text>"#,
+             sx, sy, font, start.0, start.1)?;
+    let Vector2F(ex, ey) = screen(end);
+    writeln!(out, r#"  <text x="{}" y="{}" font-size="{}" fill="red">end ({}, {}), distance {}</text>"#,
+             ex, ey, font, end.0, end.1, navigation.distance_travelled())?;
+    writeln!(out, "</svg>")
+}
+
+pub fn query(args: &[String]) {
+    let usage = "usage: day12 [<model>...] [--input <file>]
+       day12 svg <model> [--input <file>] [--output <file>]
+models: heading, waypoint, continuous-heading, continuous-waypoint";
+    let registry = Registry::default();
+    if args.first().map(String::as_str) == Some("svg") {
+        svg(&args[1..], usage, &registry);
+        return
+    }
+    let mut models = Vec::new();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => match args.next() {
+                Some(name) => file = Some(name),
+                None => {
+                    println!("{}", usage);
+                    return
+                }
+            },
+            name if !name.starts_with("--") => models.push(name),
+            _ => {
+                println!("{}", usage);
+                return
+            }
+        }
+    }
+    if models.is_empty() {
+        models.extend(&PUZZLE_MODELS)
+    }
+
+    let input = match cli::input_or(file, INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    solve(&input, &registry, &models)
+}
+
+// chart of the route under one model, for the puzzle input unless an input file is given. Written to
+// stdout unless an output file is given
+fn svg(args: &[String], usage: &str, registry: &Registry) {
+    let mut model = None;
+    let mut input = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            option @ "--input" | option @ "--output" => match args.next() {
+                Some(file) if option == "--input" => input = Some(file),
+                Some(file) => output = Some(file),
+                None => {
+                    println!("{}", usage);
+                    return
+                }
+            },
+            name if model.is_none() && !name.starts_with("--") => model = Some(name),
+            _ => {
+                println!("{}", usage);
+                return
+            }
+        }
+    }
+    let model = match model {
+        Some(model) => model,
+        None => {
+            println!("{}", usage);
+            return
+        }
+    };
+
+    let input = match cli::input_or(input, INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    let written = match output {
+        Some(file) => std::fs::File::create(file).and_then(|mut out| export_svg(&input, registry, model, &mut out)),
+        None => export_svg(&input, registry, model, &mut io::stdout())
+    };
+    if let Err(e) = written {
+        println!("Cannot export chart: {}", e)
+    }
+}
+
+pub fn solve(input: &str, registry: &Registry, models: &[&str]) {
+    for name in models {
+        let mut model = match registry.create(name) {
+            Some(model) => model,
+            None => {
+                println!("Unknown navigation model {}, known are {:?}", name, registry.names());
+                continue
+            }
+        };
+        let moves = match parse(input, model.accepted_turns()) {
+            Ok(moves) => moves,
+            Err(errors) => {
+                for error in errors {
+                    println!("Invalid instruction, {}", error)
+                }
+                continue
+            }
+        };
+
+        model.sail(&moves);
+        println!("{}: final state: {:?}, distance is {}", name, model, model.distance_travelled());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day12::*;
+
+    fn states(name: &str) -> Vec<Snapshot<Vector2F>> {
+        let moves = parse(PUZZLE_EXAMPLE, Turns::RightAngles).unwrap();
+        Registry::default().create(name).unwrap().sail(&moves)
+    }
+
+    #[test]
+    fn heading_trajectory() {
+        let positions = [(0, 0), (10, 0), (10, 3), (17, 3), (17, 3), (17, -8)];
+        let headings = [EAST, EAST, EAST, EAST, SOUTH, SOUTH];
+        let expected = positions.iter().zip(&headings).map(|(&(x, y), heading)| Snapshot {
+            position: Vector2D(x, y).to_f64(),
+            heading: heading.to_f64(),
+            waypoints: Vec::new(),
+        }).collect::<Vec<_>>();
+        assert_eq!(states("heading"), expected);
+    }
+
+    #[test]
+    fn waypoint_trajectory() {
+        let positions = [(0, 0), (100, 10), (100, 10), (170, 38), (170, 38), (214, -72)];
+        let headings = [(10, 1), (10, 1), (10, 4), (10, 4), (4, -10), (4, -10)];
+        let expected = positions.iter().zip(&headings).map(|(&(x, y), &(dx, dy))| Snapshot {
+            position: Vector2D(x, y).to_f64(),
+            heading: Vector2D(dx, dy).to_f64(),
+            waypoints: vec![Vector2D(x + dx, y + dy).to_f64()],
+        }).collect::<Vec<_>>();
+        assert_eq!(states("waypoint"), expected);
+        // turns by floating point rotation are only exact up to rounding
+        let continuous = states("continuous-waypoint").iter().map(|state| state.position.round()).collect::<Vec<_>>();
+        assert_eq!(continuous, expected.iter().map(|state| state.position.round()).collect::<Vec<_>>());
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    struct Depth(Vector2F, f64);
+
+    impl Position for Depth {
+        fn manhattan(self) -> f64 {
+            self.0.manhattan() + self.1.abs()
+        }
+
+        fn plan(self) -> Vector2F {
+            self.0
+        }
+    }
+
+    // moves along the compass directions, and dives when going forward
+    #[derive(Debug)]
+    struct Diver(Depth);
+
+    impl NavigationModel for Diver {
+        type Position = Depth;
+
+        fn apply(&mut self, movement: &Move) {
+            let Move(operation, amount) = *movement;
+            match operation.compass_direction() {
+                Some(direction) => (self.0).0 = (self.0).0 + direction.to_f64() * amount as f64,
+                None => if let Operation::Forward = operation {
+                    (self.0).1 -= amount as f64
+                }
+            }
+        }
+
+        fn position(&self) -> Depth {
+            self.0
+        }
+
+        fn heading(&self) -> Depth {
+            Depth(Vector2F(0.0, 0.0), -1.0)
+        }
+    }
+
+    #[test]
+    fn models_of_any_space() {
+        let mut registry = Registry::default();
+        registry.register("diver", || Box::new(Diver(Depth(Vector2F(0.0, 0.0), 0.0))));
+        assert_eq!(registry.names(), vec!["heading", "waypoint", "continuous-heading", "continuous-waypoint", "diver"]);
+        assert!(registry.create("rowing").is_none());
+
+        let mut diver = registry.create("diver").unwrap();
+        let moves = parse("N3\nF10\nE4", diver.accepted_turns()).unwrap();
+        let chart = diver.sail(&moves).iter().map(|state| state.position).collect::<Vec<_>>();
+        assert_eq!(chart, vec![Vector2F(0.0, 0.0), Vector2F(0.0, 3.0), Vector2F(0.0, 3.0), Vector2F(4.0, 3.0)]);
+        assert_eq!(diver.distance_travelled(), 17.0);
+        // every model is created fresh
+        assert_eq!(registry.create("diver").unwrap().distance_travelled(), 0.0);
+    }
+
+    #[test]
+    fn continuous_heading() {
+        let mut model = Registry::default().create("continuous-heading").unwrap();
+        let moves = parse("R45\nF10", model.accepted_turns()).unwrap();
+        let last = model.sail(&moves).pop().unwrap();
+        let diagonal = 10.0 / 2f64.sqrt();
+        assert!((last.position - Vector2F(diagonal, -diagonal)).euclidean() < 1e-9);
+        assert!((last.heading - Vector2F(1.0, -1.0) * (1.0 / 2f64.sqrt())).euclidean() < 1e-9);
+        assert!((model.distance_travelled() - 2.0 * diagonal).abs() < 1e-9);
+    }
+
+    #[test]
+    fn instruction_errors() {
+        let unknown = InstructionError::UnknownAction { line: 2, action: 'X' };
+        assert_eq!(parse("F10\nX3", Turns::RightAngles).unwrap_err(), vec![unknown]);
+        let invalid = |line, value: &str| InstructionError::InvalidValue { line, value: value.to_owned() };
+        assert_eq!(parse("F1x\nN-3\nE+2\nS", Turns::RightAngles).unwrap_err(),
+                   vec![invalid(1, "1x"), invalid(2, "-3"), invalid(3, "+2"), invalid(4, "")]);
+        assert_eq!(parse("R45", Turns::RightAngles).unwrap_err(), vec![InstructionError::InvalidTurn { line: 1, degrees: 45 }]);
+        assert_eq!(parse("R45", Turns::Continuous).unwrap().len(), 1);
+
+        let all = parse("L100\nF10\nQ1\n\nN7.5\nR270", Turns::RightAngles).unwrap_err();
+        assert_eq!(all, vec![
+            InstructionError::InvalidTurn { line: 1, degrees: 100 },
+            InstructionError::UnknownAction { line: 3, action: 'Q' },
+            invalid(5, "7.5"),
+        ]);
+    }
+
+    #[test]
+    fn svg_export() {
+        let mut out = Vec::new();
+        export_svg(PUZZLE_EXAMPLE, &Registry::default(), "waypoint", &mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"d="M0 0 L100 -10 L100 -10 L170 -38 L170 -38 L214 72 ""#));
+        assert!(svg.contains(r#"<circle cx="214" cy="72""#));
+        assert!(svg.contains(r#"<circle cx="218" cy="82""#));
+        assert!(svg.contains("end (214, -72), distance 286"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+
+        let unknown = export_svg(PUZZLE_EXAMPLE, &Registry::default(), "rowing", &mut Vec::new()).unwrap_err();
+        assert_eq!(unknown.kind(), ErrorKind::InvalidInput);
+        let invalid = export_svg("F10\nR45", &Registry::default(), "heading", &mut Vec::new()).unwrap_err();
+        assert_eq!(invalid.kind(), ErrorKind::InvalidData);
+    }
 }
 
+#[cfg(test)]
 pub const PUZZLE_EXAMPLE: &str = "F10
 N3
 F7
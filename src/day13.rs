@@ -1,114 +1,311 @@
-use std::ops::{Add, Mul, Sub, Neg};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{BufRead, Write};
+use std::iter;
+use std::ops::Range;
 use std::str::FromStr;
 
-use num::Integer;
-use num::integer::lcm;
-use regex::Regex;
+use num::{BigInt, ToPrimitive};
 
-#[derive(Debug, Copy, Clone)]
-struct ModuleValue {
-    value_in_modulus: i64,
-    modulus: i64,
+use crate::cli;
+use crate::crt;
+use crate::crt::{Congruence, CrtError};
+use crate::modular::ModuleValue;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Bus {
+    pub id: i64,
+    // position in the schedule, the bus should leave this many minutes after the first one
+    pub offset: i64,
 }
 
-macro_rules! arithmetics {
-    ($tpe:ty, $name:ident, $op:tt) => {
-        impl $tpe for ModuleValue {
-            type Output = ModuleValue;
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ScheduleError {
+    MissingLine,
+    InvalidArrival(String),
+    InvalidBus { position: usize, text: String },
+}
 
-            fn $name(self, rhs: ModuleValue) -> ModuleValue {
-                self.verify(rhs.modulus);
-                ModuleValue::new(self.value_in_modulus $op rhs.value_in_modulus, self.modulus)
-            }
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::MissingLine => write!(f, "expected an arrival time and a bus list"),
+            ScheduleError::InvalidArrival(text) => write!(f, "'{}' is not a valid arrival time", text),
+            ScheduleError::InvalidBus { position, text } => write!(f, "entry {} '{}' is neither a bus id nor x", position, text),
         }
-    };
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AlignmentError {
+    NotScheduled(i64),
+    Unsolvable(CrtError),
+}
+
+impl Display for AlignmentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AlignmentError::NotScheduled(id) => write!(f, "bus {} is not on the schedule", id),
+            AlignmentError::Unsolvable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// times in a window, one modulus apart. Stops early instead of overflowing
+#[derive(Debug, Clone)]
+pub struct Alignments {
+    next: Option<i64>,
+    step: Option<i64>,
+    end: i64,
+}
+
+impl Iterator for Alignments {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let t = self.next.filter(|t| *t < self.end)?;
+        self.next = self.step.and_then(|step| t.checked_add(step));
+        Some(t)
+    }
 }
 
-arithmetics!(Add, add, +);
-arithmetics!(Sub, sub, -);
-arithmetics!(Mul, mul, *);
+#[derive(Debug, Clone)]
+pub struct Timetable {
+    pub arrival: i64,
+    pub buses: Vec<Bus>,
+}
 
-impl ModuleValue {
-    fn new(value: i64, modulus: i64) -> Self {
-        let mut value_in_modulus = value % modulus;
-        if value_in_modulus < 0 {
-            value_in_modulus += modulus
+impl Timetable {
+    pub fn parse(input: &str) -> Result<Self, ScheduleError> {
+        let mut lines = input.lines().map(str::trim).filter(|l| !l.is_empty());
+        let (first, second) = match (lines.next(), lines.next()) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return Err(ScheduleError::MissingLine)
+        };
+        let arrival = i64::from_str(first).map_err(|_| ScheduleError::InvalidArrival(first.to_owned()))?;
+
+        let mut buses = Vec::new();
+        for (position, text) in second.split(',').enumerate() {
+            match (text, i64::from_str(text)) {
+                ("x", _) => (),
+                (_, Ok(id)) if id > 0 => buses.push(Bus { id, offset: position as i64 }),
+                _ => return Err(ScheduleError::InvalidBus { position, text: text.to_owned() })
+            }
         }
-        ModuleValue { value_in_modulus, modulus }
+
+        Ok(Timetable { arrival, buses })
     }
 
-    fn multiplicative_inverse(&self, plain: i64) -> ModuleValue {
-        let value_in_modulus = plain.extended_gcd(&self.modulus).x;
+    pub fn bus(&self, id: i64) -> Option<Bus> {
+        self.buses.iter().find(|b| b.id == id).copied()
+    }
+
+    // departures of the bus at or after t, as far as they fit an i64. None for a bus that is not on the schedule
+    pub fn next_departures(&self, id: i64, t: i64) -> Option<impl Iterator<Item=i64>> {
+        let bus = self.bus(id)?;
+        let first = t.checked_add(ModuleValue::new(-(t as i128), bus.id).value());
+        Some(iter::successors(first, move |departure| departure.checked_add(bus.id)))
+    }
 
-        Self::new(value_in_modulus, self.modulus)
+    // earliest bus at or after arrival, as (departure, bus id)
+    pub fn earliest_departure(&self) -> Option<(i64, i64)> {
+        self.buses.iter()
+            .map(|b| (self.arrival + ModuleValue::new(-self.arrival, b.id).value(), b.id))
+            .min()
     }
 
-    fn verify(self, m: i64) {
-        if m != self.modulus {
-            panic!("Cannot add in different moduli")
+    pub fn departing_at(&self, t: i64) -> Vec<i64> {
+        self.buses.iter().filter(|b| t % b.id == 0).map(|b| b.id).collect()
+    }
+
+    // all t in the window where each given bus leaves at t + its offset, in ascending order
+    pub fn alignments(&self, constraints: &[Bus], window: Range<i64>) -> Result<Alignments, AlignmentError> {
+        if let Some(unknown) = constraints.iter().find(|b| self.bus(b.id).is_none()) {
+            return Err(AlignmentError::NotScheduled(unknown.id));
+        }
+        let congruences = constraints.iter().map(|b| Congruence::new(-b.offset, b.id)).collect::<Vec<_>>();
+        let solution = crt::solve(&congruences).map_err(AlignmentError::Unsolvable)?;
+
+        // smallest solution not below the window start
+        let start = BigInt::from(window.start);
+        let mut candidate = &start + (&solution.value - &start) % &solution.modulus;
+        if candidate < start {
+            candidate += &solution.modulus
         }
+        Ok(Alignments { next: candidate.to_i64(), step: solution.modulus.to_i64(), end: window.end })
     }
+
+    // first time every bus leaves at its position in the schedule
+    pub fn schedule_alignment(&self) -> Result<crt::Solution, CrtError> {
+        let congruences = self.buses.iter().map(|b| Congruence::new(-b.offset, b.id)).collect::<Vec<_>>();
+        crt::solve(&congruences)
+    }
+}
+
+fn parse_constraint(text: &str) -> Option<Bus> {
+    let mut parts = text.splitn(2, ':');
+    let id = i64::from_str(parts.next()?).ok().filter(|id| *id > 0)?;
+    let offset = parts.next().map_or(Ok(0), i64::from_str).ok()?;
+    Some(Bus { id, offset })
 }
 
-impl Neg for ModuleValue {
-    type Output = ModuleValue;
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 10_000;
 
-    fn neg(self) -> Self::Output {
-        ModuleValue::new(-self.value_in_modulus, self.modulus)
+// splits an optional trailing "--limit <n>" off the arguments
+fn split_limit<'a>(args: &'a [&'a str]) -> Option<(&'a [&'a str], usize)> {
+    match args {
+        [rest @ .., "--limit", n] => usize::from_str(n).ok().filter(|n| *n <= MAX_LIMIT).map(|n| (rest, n)),
+        _ => Some((args, DEFAULT_LIMIT))
     }
 }
 
-pub fn solve(input: &str) {
-    let parse_departures_simple = Regex::new(r"(\d+)(,|$)").unwrap();
-    let parse_departures_twist = Regex::new(r"(\d+|x)(,|$)").unwrap();
-    let mut lines = input.lines();
-    let line1 = lines.next().unwrap();
-    println!("line: {}", line1);
-    let arrival = i64::from_str(line1).unwrap();
-
-    let best = parse_departures_simple.captures_iter(input).map(|d| {
-        let departure = i64::from_str(d.get(1).unwrap().as_str()).unwrap();
-        (departure - (arrival % departure), departure)
-    }).min().unwrap();
-    println!("Best departure: {:?}", best.0 * best.1);
-
-    let target = parse_departures_twist
-        .captures_iter(input)
-        .map(|d| {
-            i64::from_str(d.get(1).unwrap().as_str()).ok()
-        })
-        .enumerate()
-        .filter_map(|t| t.1.map(|inner| ModuleValue {
-            modulus: inner,
-            value_in_modulus: t.0 as i64,
-        }))
-        .collect::<Vec<_>>();
-    println!("Deltas: {:?}", target);
-    find_alignment(&target.as_slice()[1..], 0, target[0].modulus);
+fn answer(timetable: &Timetable, command: &[&str], out: &mut impl Write) -> io::Result<()> {
+    let (command, limit) = match split_limit(command) {
+        Some(split) => split,
+        None => return writeln!(out, "the limit must be a number up to {}", MAX_LIMIT)
+    };
+    let numbers = command.iter().skip(1).map(|n| i64::from_str(n).ok()).collect::<Vec<_>>();
+    match (command.first().copied(), numbers.as_slice()) {
+        (Some("next"), [Some(id), Some(t)]) => {
+            match timetable.next_departures(*id, *t) {
+                Some(departures) => writeln!(out, "{:?}", departures.take(limit).collect::<Vec<_>>()),
+                None => writeln!(out, "bus {} is not on the schedule", id)
+            }
+        }
+        (Some("at"), [Some(t)]) => writeln!(out, "{:?}", timetable.departing_at(*t)),
+        (Some("earliest"), []) => match timetable.earliest_departure() {
+            Some((departure, id)) => writeln!(out, "bus {} at {}, waiting {} minutes after arriving at {}",
+                                              id, departure, departure - timetable.arrival, timetable.arrival),
+            None => writeln!(out, "no buses")
+        },
+        (Some("schedule"), []) => match timetable.schedule_alignment() {
+            Ok(solution) => writeln!(out, "all buses align at {}, repeating every {}", solution.value, solution.modulus),
+            Err(e) => writeln!(out, "no alignment, {}", e)
+        },
+        (Some("align"), [Some(from), Some(to), ..]) if command.len() > 3 => {
+            let constraints = command[3..].iter().map(|c| parse_constraint(c)).collect::<Option<Vec<_>>>();
+            match constraints.map(|c| timetable.alignments(&c, *from..*to)) {
+                Some(Ok(times)) => {
+                    let mut times = times.take(limit + 1).collect::<Vec<_>>();
+                    if times.len() > limit {
+                        times.truncate(limit);
+                        writeln!(out, "first {} alignments: {:?}", limit, times)
+                    } else {
+                        writeln!(out, "{} alignments: {:?}", times.len(), times)
+                    }
+                }
+                Some(Err(e)) => writeln!(out, "no alignment, {}", e),
+                None => writeln!(out, "constraints are written as <bus id>[:<offset>]")
+            }
+        }
+        _ => {
+            writeln!(out, "commands, listing up to {} results unless followed by --limit <n>:", DEFAULT_LIMIT)?;
+            writeln!(out, "  next <bus> <t>                  departures of a bus at or after t")?;
+            writeln!(out, "  at <t>                          buses leaving at t")?;
+            writeln!(out, "  earliest                        first bus leaving after the arrival")?;
+            writeln!(out, "  schedule                        first time the whole schedule lines up")?;
+            writeln!(out, "  align <from> <to> <bus>[:<offset>]...")?;
+            writeln!(out, "                                  times in [from, to) where each bus leaves at time + offset")
+        }
+    }
 }
 
-fn find_alignment(targets: &[ModuleValue], first: i64, stride: i64) {
-    if targets.is_empty() {
-        println!("Found match at {}", first);
-        return
-    }
-    /*
-    mod target.modulus:
-    start + target.value_in_module + a * stride = 0
-    a * stride = -(start + target.value_in_module) (:= k)
-    a = k * invert(stride)
-     */
-
-    let target = targets[0];
-    let start = ModuleValue::new(first, target.modulus);
-    let k = -(start + target);
-    let invert = k.multiplicative_inverse(stride);
-    let a = k * invert;
-
-    find_alignment(&targets[1..], first + a.value_in_modulus * stride, stride * target.modulus);
+// answers commands from stdin until it is closed
+pub fn query(args: &[String]) {
+    let input = match cli::input_or(args.first(), INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    let timetable = match Timetable::parse(&input) {
+        Ok(timetable) => timetable,
+        Err(e) => {
+            println!("Invalid schedule: {}", e);
+            return
+        }
+    };
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                println!("Cannot read command: {}", e);
+                return
+            }
+        };
+        let command = line.split_whitespace().collect::<Vec<_>>();
+        if command.is_empty() {
+            continue
+        }
+        if answer(&timetable, &command, &mut out).is_err() {
+            return
+        }
+    }
 }
 
+#[cfg(test)]
+mod test {
+    use crate::day13::*;
+
+    #[test]
+    fn queries() {
+        let timetable = Timetable::parse(EXAMPLE_INPUT).unwrap();
+        assert_eq!(timetable.earliest_departure(), Some((944, 59)));
+        let next = |id, t, count| timetable.next_departures(id, t).map(|d| d.take(count).collect::<Vec<_>>());
+        assert_eq!(next(7, 939, 3), Some(vec![945, 952, 959]));
+        assert_eq!(next(7, 945, 1), Some(vec![945]));
+        assert_eq!(next(8, 939, 3), None);
+        assert_eq!(next(7, i64::MAX - 10, 5), Some(vec![i64::MAX - 7, i64::MAX]));
+        assert_eq!(next(59, i64::MAX - 10, 5), Some(vec![]));
+        assert_eq!(next(59, i64::MIN, 1), Some(vec![i64::MIN + 32]));
+        assert_eq!(timetable.departing_at(7 * 13 * 2), vec![7, 13]);
+        assert_eq!(timetable.schedule_alignment().unwrap().value, 1068781.into());
+    }
+
+    #[test]
+    fn alignments() {
+        let timetable = Timetable::parse(EXAMPLE_INPUT).unwrap();
+        let buses = [Bus { id: 7, offset: 0 }, Bus { id: 13, offset: 1 }];
+        let aligned = |constraints: &[Bus], window| timetable.alignments(constraints, window).map(Iterator::collect::<Vec<_>>);
+        assert_eq!(aligned(&buses, 0..250), Ok(vec![77, 168]));
+        assert_eq!(aligned(&buses, -100..0), Ok(vec![-14]));
+        assert_eq!(aligned(&timetable.buses, 0..1068781), Ok(vec![]));
+        assert_eq!(aligned(&timetable.buses, 0..1068782), Ok(vec![1068781]));
+        // a lazy sequence, no matter how wide the window
+        let wide = timetable.alignments(&[Bus { id: 7, offset: 0 }], 0..i64::MAX).unwrap();
+        assert_eq!(wide.take(3).collect::<Vec<_>>(), vec![0, 7, 14]);
+        let last = timetable.alignments(&[Bus { id: 7, offset: 0 }], i64::MAX - 10..i64::MAX).unwrap();
+        assert_eq!(last.collect::<Vec<_>>(), vec![i64::MAX - 7]);
+
+        let contradicting = [Bus { id: 7, offset: 0 }, Bus { id: 7, offset: 1 }];
+        assert_eq!(aligned(&contradicting, 0..100), Err(AlignmentError::Unsolvable(CrtError::Inconsistent { index: 1 })));
+        assert_eq!(aligned(&[Bus { id: 4, offset: 0 }], 0..100), Err(AlignmentError::NotScheduled(4)));
+        assert_eq!(Timetable::parse("939\n7,y").unwrap_err(), ScheduleError::InvalidBus { position: 1, text: "y".to_owned() });
+    }
+
+    #[test]
+    fn commands() {
+        let timetable = Timetable::parse(EXAMPLE_INPUT).unwrap();
+        let run = |command: &str| {
+            let mut out = Vec::new();
+            answer(&timetable, &command.split_whitespace().collect::<Vec<_>>(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(run("next 7 939 --limit 3"), "[945, 952, 959]\n");
+        assert_eq!(run("align 0 9223372036854775807 7"), "first 10 alignments: [0, 7, 14, 21, 28, 35, 42, 49, 56, 63]\n");
+        assert_eq!(run("align 0 20 7 --limit 5"), "3 alignments: [0, 7, 14]\n");
+        assert_eq!(run("align 0 20 8"), "no alignment, bus 8 is not on the schedule\n");
+        assert_eq!(run("at 91 --limit 20000"), "the limit must be a number up to 10000\n");
+        assert_eq!(run("earliest"), "bus 59 at 944, waiting 5 minutes after arriving at 939\n");
+        assert_eq!(run("schedule"), "all buses align at 1068781, repeating every 3162341\n");
+    }
+}
 
+#[cfg(test)]
 pub const EXAMPLE_INPUT: &str = "939
 7,13,x,x,59,x,31,19";
 
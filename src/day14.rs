@@ -1,102 +1,462 @@
-use regex::Regex;
-use std::collections::HashMap;
-use std::mem;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
 
-pub fn solve(input: &str) {
-    let parse_re = Regex::new(r"(mask = ([01X]{36}))|(mem\[(\d+)] = (\d+))").unwrap();
-    solve_part_1(input, &parse_re);
-    solve_part_2(input, &parse_re);
+use num::{BigUint, One};
+
+use crate::cli;
+
+pub const PUZZLE_WIDTH: u32 = 36;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Decoder {
+    // the mask modifies the written value
+    V1,
+    // the mask modifies the address, floating bits write to every combination
+    V2,
 }
 
-#[derive(Copy, Clone,Debug)]
-enum MaskValue { UNCHANGED, SET1, FLOATING }
-struct Mask([MaskValue;36]);
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ProgramError {
+    InvalidWidth(u32),
+    Syntax { line: usize, text: String },
+    MaskWidth { line: usize, found: usize, width: u32 },
+    OutOfRange { line: usize, number: String },
+}
+
+impl Display for ProgramError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::InvalidWidth(width) => write!(f, "word width {} is not between 1 and 128", width),
+            ProgramError::Syntax { line, text } => write!(f, "line {}: cannot parse '{}'", line, text),
+            ProgramError::MaskWidth { line, found, width } => write!(f, "line {}: mask has {} bits, words have {}", line, found, width),
+            ProgramError::OutOfRange { line, number } => write!(f, "line {}: {} does not fit into a word", line, number),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Mask {
+    ones: u128,
+    zeros: u128,
+    floating: u128,
+}
 
 impl Mask {
-    fn new(input: &str) -> Self {
-        let bytes = input.as_bytes();
-        let mut values = [MaskValue::UNCHANGED; 36];
-        for i in 0..36 {
-            values[i] = match bytes[i] {
-                b'1' => MaskValue::SET1,
-                b'X' => MaskValue::FLOATING,
-                _ => MaskValue::UNCHANGED
+    fn new(text: &str) -> Option<Self> {
+        let mut mask = Mask::default();
+        for byte in text.bytes() {
+            mask.ones <<= 1;
+            mask.zeros <<= 1;
+            mask.floating <<= 1;
+            match byte {
+                b'1' => mask.ones |= 1,
+                b'0' => mask.zeros |= 1,
+                b'X' => mask.floating |= 1,
+                _ => return None
             }
         }
-        Mask(values)
-    }
-
-    fn indices(&self, base: usize) -> Vec<usize> {
-        let mut cursor = 1;
-        let mut result = vec![base];
-        for value in self.0.iter().rev() {
-            match value {
-                MaskValue::UNCHANGED => (),
-                MaskValue::SET1 => for v in result.iter_mut() {
-                    *v |= cursor
-                }
-                MaskValue::FLOATING => {
-                    let mut r2 = Vec::with_capacity(2 * result.capacity());
-                    for v in result {
-                        r2.push(v & (!cursor));
-                        r2.push(v | cursor);
-                    }
-                    result = r2;
-                }
+        Some(mask)
+    }
+
+    fn value(self, value: u128) -> u128 {
+        (value | self.ones) & !self.zeros
+    }
+
+    // the addresses written to by a decoder version 2 write to base
+    fn pattern(self, base: u128) -> Pattern {
+        let care = !self.floating;
+        Pattern { value: (base | self.ones) & care, care }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Instruction {
+    Mask(Mask),
+    Write { address: u128, value: u128 },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Line {
+    pub number: usize,
+    pub instruction: Instruction,
+}
+
+fn word(width: u32) -> u128 {
+    u128::MAX >> (128 - width)
+}
+
+// an unusable width is reported on its own, otherwise every malformed line
+pub fn parse(input: &str, width: u32) -> Result<Vec<Line>, Vec<ProgramError>> {
+    if width == 0 || width > 128 {
+        return Err(vec![ProgramError::InvalidWidth(width)]);
+    }
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, text) in input.lines().enumerate() {
+        let number = index + 1;
+        let text = text.trim();
+        if text.is_empty() {
+            continue
+        }
+        let syntax = || ProgramError::Syntax { line: number, text: text.to_owned() };
+        let word_sized = |digits: &str| match u128::from_str(digits) {
+            Ok(n) if n <= word(width) => Ok(n),
+            Ok(_) => Err(ProgramError::OutOfRange { line: number, number: digits.to_owned() }),
+            Err(_) => Err(syntax())
+        };
+
+        let instruction = if let Some(bits) = text.strip_prefix("mask = ") {
+            if bits.len() != width as usize {
+                Err(ProgramError::MaskWidth { line: number, found: bits.len(), width })
+            } else {
+                Mask::new(bits).map(Instruction::Mask).ok_or_else(syntax)
+            }
+        } else {
+            let assignment = text.strip_prefix("mem[").and_then(|rest| {
+                let mut parts = rest.splitn(2, "] = ");
+                Some((parts.next()?, parts.next()?))
+            });
+            match assignment {
+                Some((address, value)) => word_sized(address)
+                    .and_then(|address| Ok(Instruction::Write { address, value: word_sized(value)? })),
+                None => Err(syntax())
             }
-            cursor <<= 1
+        };
+
+        match instruction {
+            Ok(instruction) => lines.push(Line { number, instruction }),
+            Err(e) => errors.push(e)
         }
-        result
     }
+
+    if errors.is_empty() {
+        Ok(lines)
+    } else {
+        Err(errors)
+    }
+}
+
+// all addresses that agree with value on the bits in care, the other bits float
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Pattern {
+    value: u128,
+    care: u128,
 }
 
+impl Pattern {
+    fn size(self) -> BigUint {
+        BigUint::one() << (128 - self.care.count_ones()) as usize
+    }
 
-fn solve_part_2(input: &str, parse_re: &Regex) {
-    let mut mask = Mask([MaskValue::UNCHANGED; 36]);
-    let mut memory = HashMap::<usize, u64>::new();
+    pub fn contains(self, address: u128) -> bool {
+        address & self.care == self.value
+    }
 
-    for next in parse_re.captures_iter(input) {
-        if let Some(c) = next.get(2) {
-            mask = Mask::new(c.as_str())
-        } else {
-            let location: usize = next.get(4).unwrap().as_str().parse().unwrap();
-            let value: u64 = next.get(5).unwrap().as_str().parse().unwrap();
-            for offset in mask.indices(location) {
-                memory.insert(offset, value);
+    fn intersects(self, other: Pattern) -> bool {
+        (self.value ^ other.value) & self.care & other.care == 0
+    }
+
+    // disjoint patterns covering every address in self but not in other
+    fn subtract(self, other: Pattern) -> Vec<Pattern> {
+        if !self.intersects(other) {
+            return vec![self];
+        }
+
+        let mut remainder = Vec::new();
+        let mut narrowed = self;
+        let mut open = other.care & !self.care;
+        // fix the bits only other cares about one at a time: opposite to other is outside of it,
+        // equal to other continues with the part that still overlaps
+        while open != 0 {
+            let bit = open & open.wrapping_neg();
+            open &= !bit;
+            narrowed.care |= bit;
+            remainder.push(Pattern { value: (narrowed.value & !bit) | (!other.value & bit), care: narrowed.care });
+            narrowed.value |= other.value & bit;
+        }
+        remainder
+    }
+
+    fn render(self, width: u32) -> String {
+        (0..width).rev().map(|bit| match (self.care >> bit & 1, self.value >> bit & 1) {
+            (0, _) => 'X',
+            (_, 1) => '1',
+            _ => '0'
+        }).collect()
+    }
+}
+
+// memory written by address patterns, stored as disjoint regions with a common value
+#[derive(Debug, Default)]
+struct PatternMemory {
+    regions: Vec<(Pattern, u128)>,
+}
+
+impl PatternMemory {
+    fn write(&mut self, pattern: Pattern, value: u128) {
+        let mut regions = Vec::with_capacity(self.regions.len() + 1);
+        for (region, old) in self.regions.drain(..) {
+            regions.extend(region.subtract(pattern).into_iter().map(|r| (r, old)));
+        }
+        if value != 0 {
+            regions.push((pattern, value));
+        }
+        self.regions = regions;
+    }
+
+    fn read(&self, address: u128) -> u128 {
+        self.regions.iter().find(|(region, _)| region.contains(address)).map_or(0, |(_, value)| *value)
+    }
+
+    fn sum(&self) -> BigUint {
+        self.regions.iter().map(|(region, value)| region.size() * *value).sum()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct WriteRecord {
+    pub line: usize,
+    pub addresses: Pattern,
+    pub value: u128,
+}
+
+#[derive(Debug)]
+pub struct Emulator {
+    width: u32,
+    decoder: Decoder,
+    mask: Mask,
+    memory: PatternMemory,
+    writes: Vec<WriteRecord>,
+}
+
+impl Emulator {
+    // width has to be between 1 and 128, as checked by parse
+    pub fn new(width: u32, decoder: Decoder) -> Self {
+        assert!(width > 0 && width <= 128, "{}", ProgramError::InvalidWidth(width));
+        Emulator { width, decoder, mask: Mask::default(), memory: PatternMemory::default(), writes: Vec::new() }
+    }
+
+    pub fn execute(&mut self, line: &Line) {
+        match line.instruction {
+            Instruction::Mask(mask) => self.mask = mask,
+            Instruction::Write { address, value } => {
+                let (addresses, value) = match self.decoder {
+                    Decoder::V1 => (Pattern { value: address, care: u128::MAX }, self.mask.value(value)),
+                    Decoder::V2 => (self.mask.pattern(address), value)
+                };
+                let value = value & word(self.width);
+                self.memory.write(addresses, value);
+                self.writes.push(WriteRecord { line: line.number, addresses, value })
             }
         }
     }
 
-    println!("Memory contents: {}", memory.values().sum::<u64>())
+    pub fn run(&mut self, program: &[Line]) {
+        for line in program {
+            self.execute(line)
+        }
+    }
+
+    pub fn read(&self, address: u128) -> u128 {
+        self.memory.read(address)
+    }
+
+    pub fn sum(&self) -> BigUint {
+        self.memory.sum()
+    }
+
+    // every write that touched the address, oldest first
+    pub fn history(&self, address: u128) -> Vec<WriteRecord> {
+        self.writes.iter().filter(|w| w.addresses.contains(address)).copied().collect()
+    }
+
+    // non-zero memory, floating address bits written as X
+    pub fn dump<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut regions = self.memory.regions.clone();
+        regions.sort_by_key(|(region, _)| region.value);
+        for (region, value) in regions {
+            if region.care == u128::MAX {
+                writeln!(out, "{:>12} = {}", region.value, value)?;
+            } else {
+                writeln!(out, "{} = {} ({} addresses)", region.render(self.width), value, region.size())?;
+            }
+        }
+        Ok(())
+    }
 }
 
-fn solve_part_1(input: &str, parse_re: &Regex) {
-    let mut set_mask: u64 = 0;
-    let mut unset_mask: u64 = 0;
-    let mut memory = HashMap::<usize, u64>::new();
-
-    for next in parse_re.captures_iter(input) {
-        if let Some(c) = next.get(2) {
-            let mut i = 1;
-            set_mask = 0;
-            unset_mask = 0;
-
-            for char in c.as_str().bytes().rev() {
-                match char {
-                    b'1' => set_mask |= i,
-                    b'0' => unset_mask |= i,
-                    _ => ()
-                }
-                i <<= 1
+fn load(input: &str, width: u32, decoder: Decoder) -> Option<Emulator> {
+    match parse(input, width) {
+        Ok(program) => {
+            let mut emulator = Emulator::new(width, decoder);
+            emulator.run(&program);
+            Some(emulator)
+        }
+        Err(errors) => {
+            for error in errors {
+                println!("Invalid program, {}", error)
+            }
+            None
+        }
+    }
+}
+
+pub fn solve(input: &str) {
+    for decoder in &[Decoder::V1, Decoder::V2] {
+        if let Some(emulator) = load(input, PUZZLE_WIDTH, *decoder) {
+            println!("Memory contents: {}", emulator.sum())
+        }
+    }
+}
+
+pub fn query(args: &[String]) {
+    let usage = "usage: day14 [<v1|v2> <width> <sum|dump|read <address>|history <address>> [program file]]";
+    if args.is_empty() {
+        solve(INPUT);
+        return
+    }
+    let decoder = match args.first().map(String::as_str) {
+        Some("v1") => Decoder::V1,
+        Some("v2") => Decoder::V2,
+        _ => {
+            println!("{}", usage);
+            return
+        }
+    };
+    let width = match args.get(1).and_then(|w| u32::from_str(w).ok()) {
+        Some(width) => width,
+        None => {
+            println!("{}", usage);
+            return
+        }
+    };
+    let (command, address, file) = match (args.get(2).map(String::as_str), args.get(3).map(|a| u128::from_str(a))) {
+        (Some(command @ "read"), Some(Ok(address))) | (Some(command @ "history"), Some(Ok(address))) => (command, address, args.get(4)),
+        (Some(command @ "sum"), _) | (Some(command @ "dump"), _) => (command, 0, args.get(3)),
+        _ => {
+            println!("{}", usage);
+            return
+        }
+    };
+    let input = match cli::input_or(file, INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    let emulator = match load(&input, width, decoder) {
+        Some(emulator) => emulator,
+        None => return
+    };
+
+    match command {
+        "sum" => println!("Memory contents: {}", emulator.sum()),
+        "read" => println!("mem[{}] = {}", address, emulator.read(address)),
+        "history" => for write in emulator.history(address) {
+            println!("line {:>4}: {} = {}", write.line, write.addresses.render(width), write.value)
+        },
+        _ => {
+            if let Err(e) = emulator.dump(&mut io::stdout()) {
+                println!("Cannot write dump: {}", e)
             }
-        } else {
-            let location: usize = next.get(4).unwrap().as_str().parse().unwrap();
-            let value: u64 = next.get(5).unwrap().as_str().parse().unwrap();
-            memory.insert(location, (value | set_mask) & (!unset_mask));
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
 
-    println!("Memory contents: {}", memory.values().sum::<u64>())
+    use num::BigUint;
+
+    use crate::day14::*;
+
+    const EXAMPLE_V1: &str = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
+mem[8] = 11
+mem[7] = 101
+mem[8] = 0";
+
+    const EXAMPLE_V2: &str = "mask = 000000000000000000000000000000X1001X
+mem[42] = 100
+mask = 00000000000000000000000000000000X0XX
+mem[26] = 1";
+
+    fn pattern(text: &str) -> Pattern {
+        let mask = Mask::new(text).unwrap();
+        Pattern { value: mask.ones, care: !mask.floating }
+    }
+
+    fn sum_by_expansion(writes: &[(Pattern, u128)]) -> u128 {
+        let mut memory = HashMap::new();
+        for (pattern, value) in writes {
+            for address in (0..64).filter(|a| pattern.contains(*a)) {
+                memory.insert(address, *value);
+            }
+        }
+        memory.values().sum()
+    }
+
+    #[test]
+    fn subtraction_is_exact() {
+        let a = pattern("X1XX");
+        let b = pattern("1XX1");
+        let rest = a.subtract(b);
+        assert_eq!(rest.iter().map(|p| p.size()).sum::<BigUint>(), a.size() - 2u32);
+        assert!(rest.iter().all(|p| !p.intersects(b)));
+        assert_eq!(b.subtract(b), vec![]);
+        assert_eq!(a.subtract(pattern("10000000")), vec![a]);
+
+        let writes = [(a, 3), (b, 5), (pattern("1XXXX"), 7), (pattern("10010"), 0)];
+        let mut memory = PatternMemory::default();
+        for (pattern, value) in &writes {
+            memory.write(*pattern, *value);
+        }
+        assert_eq!(memory.sum(), sum_by_expansion(&writes).into());
+    }
+
+    #[test]
+    fn decoders() {
+        let mut v1 = Emulator::new(PUZZLE_WIDTH, Decoder::V1);
+        v1.run(&parse(EXAMPLE_V1, PUZZLE_WIDTH).unwrap());
+        assert_eq!(v1.sum(), 165u32.into());
+        assert_eq!(v1.read(8), 64);
+        assert_eq!(v1.history(8).iter().map(|w| (w.line, w.value)).collect::<Vec<_>>(), vec![(2, 73), (4, 64)]);
+
+        let mut v2 = Emulator::new(PUZZLE_WIDTH, Decoder::V2);
+        v2.run(&parse(EXAMPLE_V2, PUZZLE_WIDTH).unwrap());
+        assert_eq!(v2.sum(), 208u32.into());
+        assert_eq!(v2.read(59), 100);
+        assert_eq!(v2.read(27), 1);
+        assert_eq!(v2.history(26).len(), 2);
+    }
+
+    #[test]
+    fn wide_words() {
+        let floating = format!("mask = {}\nmem[0] = 3\nmask = {}1\nmem[0] = 5", "X".repeat(128), "X".repeat(127));
+        let mut emulator = Emulator::new(128, Decoder::V2);
+        emulator.run(&parse(&floating, 128).unwrap());
+        assert_eq!(emulator.sum(), (BigUint::one() << 127usize) * 8u32);
+        assert_eq!(emulator.read(u128::MAX - 1), 3);
+
+        assert_eq!(parse("mask = 1X0", 4), Err(vec![ProgramError::MaskWidth { line: 1, found: 3, width: 4 }]));
+        assert_eq!(parse("mem[16] = 1\nmem[1] = 2", 4), Err(vec![ProgramError::OutOfRange { line: 1, number: "16".to_owned() }]));
+        assert_eq!(parse("", 129), Err(vec![ProgramError::InvalidWidth(129)]));
+    }
+
+    #[test]
+    fn many_floating_bits() {
+        let program = "mask = 000000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 1
+mask = 000000XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1
+mem[0] = 2
+mask = 000000000000000000000000000000000000
+mem[42] = 5";
+        let mut emulator = Emulator::new(36, Decoder::V2);
+        emulator.run(&parse(program, 36).unwrap());
+        assert_eq!(emulator.sum(), BigUint::from((1u64 << 29) + 2 * (1 << 29) - 1 + 5));
+    }
 }
 
 pub const INPUT: &str = "
@@ -1,33 +1,516 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::mem;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-pub fn solve(input: &str) {
-    let mut numbers = input
-        .split(',')
-        .map(|s| u64::from_str(s).unwrap())
-        .collect::<Vec<_>>();
-    let mut ledger_vec = Vec::new();
-    ledger_vec.resize(40 << 20 /* 160 mb initial working memory */, -1i32);
-    for i in 0..numbers.len() -1 {
-        ledger_vec[numbers[i] as usize] = (i + 1) as i32;
-    }
-
-    while numbers.len() < 30000000 {
-        let previous_number = *numbers.last().unwrap() as usize;
-        if ledger_vec.len() <= previous_number {
-            ledger_vec.resize(2 * ledger_vec.len(), -1);
-        }
-        let current_index = numbers.len();
-        let number_to_say = if ledger_vec[previous_number] == -1 {
-            0
+// numbers below this are tracked in a flat array, 64 mb at most
+pub const DENSE_LIMIT: u32 = 16 << 20;
+
+// turn on which each number was last spoken, 0 for never
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    dense: Vec<u32>,
+    sparse: HashMap<u32, u32>,
+    dense_limit: u32,
+}
+
+impl Ledger {
+    pub fn new(dense_limit: u32) -> Self {
+        Ledger { dense: Vec::new(), sparse: HashMap::new(), dense_limit }
+    }
+
+    pub fn get(&self, number: u32) -> u32 {
+        if number < self.dense_limit {
+            self.dense.get(number as usize).copied().unwrap_or(0)
         } else {
-            (current_index - ledger_vec[previous_number] as usize)
-        } as u64;
+            self.sparse.get(&number).copied().unwrap_or(0)
+        }
+    }
+
+    // records the turn, returning the previous one
+    pub fn replace(&mut self, number: u32, turn: u32) -> u32 {
+        if number < self.dense_limit {
+            let index = number as usize;
+            if index >= self.dense.len() {
+                // grow geometrically, but never past the dense region
+                let len = (index + 1).max(2 * self.dense.len()).min(self.dense_limit as usize);
+                self.dense.resize(len, 0);
+            }
+            mem::replace(&mut self.dense[index], turn)
+        } else {
+            self.sparse.insert(number, turn).unwrap_or(0)
+        }
+    }
+
+    // every number spoken so far, with its last turn
+    pub fn entries(&self) -> impl Iterator<Item=(u32, u32)> + '_ {
+        let dense = self.dense.iter().enumerate().filter(|(_, turn)| **turn != 0).map(|(n, turn)| (n as u32, *turn));
+        dense.chain(self.sparse.iter().map(|(n, turn)| (*n, *turn)))
+    }
+}
+
+// the spoken numbers, one per turn. Ends once the turn no longer fits an u32
+#[derive(Debug, Clone)]
+pub struct MemoryGame {
+    starting: Vec<u32>,
+    ledger: Ledger,
+    turn: u32,
+    upcoming: u32,
+}
+
+impl MemoryGame {
+    pub fn new(starting: Vec<u32>) -> Self {
+        Self::with_dense_limit(starting, DENSE_LIMIT)
+    }
+
+    pub fn with_dense_limit(starting: Vec<u32>, dense_limit: u32) -> Self {
+        MemoryGame { starting, ledger: Ledger::new(dense_limit), turn: 0, upcoming: 0 }
+    }
+
+    // number of turns played so far
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    // the number spoken next. Also the gap since the last number was spoken before that, 0 if it was new
+    pub fn upcoming(&self) -> u32 {
+        self.upcoming
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn starting(&self) -> &[u32] {
+        &self.starting
+    }
+}
+
+impl Iterator for MemoryGame {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let turn = self.turn.checked_add(1)?;
+        let spoken = self.starting.get(self.turn as usize).copied().unwrap_or(self.upcoming);
+        let previous = self.ledger.replace(spoken, turn);
+        self.upcoming = if previous == 0 { 0 } else { turn - previous };
+        self.turn = turn;
+        Some(spoken)
+    }
+}
+
+pub fn parse(input: &str) -> Result<Vec<u32>, ParseIntError> {
+    input.split(',').map(|s| u32::from_str(s.trim())).collect()
+}
+
+// the number spoken on the given turn, counting from 1
+pub fn spoken_on(starting: &[u32], turn: u32) -> Option<u32> {
+    MemoryGame::new(starting.to_vec()).nth(turn.checked_sub(1)? as usize)
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Occurrences {
+    pub first: u32,
+    pub count: u32,
+}
+
+// a number spoken again after the longest pause
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Gap {
+    pub number: u32,
+    pub from: u32,
+    pub to: u32,
+}
+
+// numbers below this get their own frequency and first occurrence, larger ones are only counted as distinct
+pub const TRACKED_LIMIT: u32 = 1 << 16;
+
+// the first multiple of every after turn
+fn next_multiple(turn: u32, every: u32) -> u32 {
+    (turn / every + 1).saturating_mul(every)
+}
+
+// a game together with statistics over all turns played
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    game: MemoryGame,
+    // indexed by number, for the numbers below tracked
+    occurrences: Vec<Occurrences>,
+    tracked: u32,
+    distinct: u32,
+    longest_gap: Option<Gap>,
+    zeros: u32,
+    sample_every: u32,
+    next_sample: u32,
+    // proportion of zeros among the first n turns, every sample_every turns
+    zero_proportion: Vec<(u32, f64)>,
+}
+
+impl Analysis {
+    pub fn new(game: MemoryGame, sample_every: u32) -> Self {
+        Self::with_tracking(game, sample_every, TRACKED_LIMIT)
+    }
+
+    pub fn with_tracking(game: MemoryGame, sample_every: u32, tracked: u32) -> Self {
+        let sample_every = sample_every.max(1);
+        Analysis {
+            next_sample: next_multiple(game.turn(), sample_every),
+            game,
+            occurrences: Vec::new(),
+            tracked,
+            distinct: 0,
+            longest_gap: None,
+            zeros: 0,
+            sample_every,
+            zero_proportion: Vec::new(),
+        }
+    }
+
+    pub fn game(&self) -> &MemoryGame {
+        &self.game
+    }
+
+    // None for numbers never spoken, and for those not tracked
+    pub fn occurrences(&self, number: u32) -> Option<Occurrences> {
+        self.occurrences.get(number as usize).copied().filter(|o| o.count > 0)
+    }
+
+    // all tracked numbers seen, most frequent first
+    pub fn by_frequency(&self) -> Vec<(u32, Occurrences)> {
+        let mut all = self.occurrences.iter().enumerate()
+            .filter(|(_, o)| o.count > 0)
+            .map(|(n, o)| (n as u32, *o))
+            .collect::<Vec<_>>();
+        all.sort_by_key(|(n, o)| (std::cmp::Reverse(o.count), *n));
+        all
+    }
+
+    pub fn distinct(&self) -> u32 {
+        self.distinct
+    }
+
+    pub fn longest_gap(&self) -> Option<Gap> {
+        self.longest_gap
+    }
+
+    pub fn zero_proportion(&self) -> &[(u32, f64)] {
+        &self.zero_proportion
+    }
+
+    pub fn step(&mut self) -> Option<u32> {
+        let spoken = self.game.next()?;
+        let turn = self.game.turn();
+        let gap = self.game.upcoming();
+
+        if gap == 0 {
+            self.distinct += 1
+        }
+        if spoken < self.tracked {
+            let index = spoken as usize;
+            if index >= self.occurrences.len() {
+                self.occurrences.resize(index + 1, Occurrences::default())
+            }
+            let occurrences = &mut self.occurrences[index];
+            if occurrences.count == 0 {
+                occurrences.first = turn
+            }
+            occurrences.count += 1;
+        }
+        if gap != 0 && !matches!(self.longest_gap, Some(longest) if longest.to - longest.from >= gap) {
+            self.longest_gap = Some(Gap { number: spoken, from: turn - gap, to: turn })
+        }
+        if spoken == 0 {
+            self.zeros += 1
+        }
+        if turn == self.next_sample {
+            self.zero_proportion.push((turn, self.zeros as f64 / turn as f64));
+            self.next_sample = next_multiple(turn, self.sample_every);
+        }
+        Some(spoken)
+    }
+
+    // plays until the given turn, saving a checkpoint to the file every given number of turns
+    pub fn run(&mut self, turns: u32, checkpoint: Option<(u32, &Path)>) -> io::Result<()> {
+        let mut due = checkpoint.map(|(every, file)| (next_multiple(self.game.turn(), every.max(1)), every.max(1), file));
+        while self.game.turn() < turns && self.step().is_some() {
+            if let Some((next, every, file)) = due.as_mut() {
+                if self.game.turn() == *next {
+                    self.save(file)?;
+                    *next = next_multiple(*next, *every);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // writes to a temporary file next to it first, so a crash never leaves a truncated checkpoint behind
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        let mut name = file.as_os_str().to_owned();
+        name.push(".partial");
+        let temporary = PathBuf::from(name);
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        self.write_checkpoint(&mut out)?;
+        out.into_inner()?.sync_all()?;
+        fs::rename(temporary, file)
+    }
+
+    pub fn write_checkpoint<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let game = &self.game;
+        let starting = game.starting.iter().map(ToString::to_string).collect::<Vec<_>>();
+        writeln!(out, "starting {}", starting.join(","))?;
+        writeln!(out, "state {} {} {}", game.turn, game.upcoming, game.ledger.dense_limit)?;
+        writeln!(out, "zeros {} {}", self.zeros, self.sample_every)?;
+        writeln!(out, "tracking {} {}", self.tracked, self.distinct)?;
+        if let Some(gap) = self.longest_gap {
+            writeln!(out, "gap {} {} {}", gap.number, gap.from, gap.to)?;
+        }
+        for (turn, proportion) in &self.zero_proportion {
+            writeln!(out, "sample {} {}", turn, proportion)?;
+        }
+        // number, first turn, count
+        for (number, occurrences) in self.by_frequency() {
+            writeln!(out, "seen {} {} {}", number, occurrences.first, occurrences.count)?;
+        }
+        // number, last turn
+        for (number, last) in game.ledger.entries() {
+            writeln!(out, "{} {}", number, last)?;
+        }
+        Ok(())
+    }
+
+    pub fn resume(file: &Path) -> io::Result<Self> {
+        Self::read_checkpoint(BufReader::new(File::open(file)?))
+    }
+
+    pub fn read_checkpoint<R: BufRead>(input: R) -> io::Result<Self> {
+        let invalid = |line: &str| io::Error::new(ErrorKind::InvalidData, format!("invalid checkpoint line '{}'", line));
+        let mut analysis = Analysis::new(MemoryGame::new(Vec::new()), 1);
+
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or("");
+            if keyword == "starting" {
+                analysis.game.starting = parse(words.next().unwrap_or("")).map_err(|_| invalid(&line))?;
+                continue
+            }
+            if keyword == "sample" {
+                let turn = words.next().and_then(|w| u32::from_str(w).ok());
+                let proportion = words.next().and_then(|w| f64::from_str(w).ok());
+                match (turn, proportion) {
+                    (Some(turn), Some(proportion)) => analysis.zero_proportion.push((turn, proportion)),
+                    _ => return Err(invalid(&line))
+                }
+                continue
+            }
+
+            let numbers = words.map(u32::from_str).collect::<Result<Vec<_>, _>>().map_err(|_| invalid(&line))?;
+            match (keyword, numbers.as_slice()) {
+                ("state", [turn, upcoming, dense_limit]) => {
+                    analysis.game.turn = *turn;
+                    analysis.game.upcoming = *upcoming;
+                    analysis.game.ledger = Ledger::new(*dense_limit);
+                }
+                ("zeros", [zeros, sample_every]) => {
+                    analysis.zeros = *zeros;
+                    analysis.sample_every = (*sample_every).max(1);
+                }
+                ("tracking", [tracked, distinct]) => {
+                    analysis.tracked = *tracked;
+                    analysis.distinct = *distinct;
+                }
+                ("seen", [number, first, count]) => {
+                    let index = *number as usize;
+                    if index >= analysis.occurrences.len() {
+                        analysis.occurrences.resize(index + 1, Occurrences::default())
+                    }
+                    analysis.occurrences[index] = Occurrences { first: *first, count: *count };
+                }
+                ("gap", [number, from, to]) => analysis.longest_gap = Some(Gap { number: *number, from: *from, to: *to }),
+                (number, [last]) => {
+                    let number = u32::from_str(number).map_err(|_| invalid(&line))?;
+                    analysis.game.ledger.replace(number, *last);
+                }
+                _ => return Err(invalid(&line))
+            }
+        }
+        analysis.next_sample = next_multiple(analysis.game.turn, analysis.sample_every);
+        Ok(analysis)
+    }
+
+    pub fn report<W: Write>(&self, out: &mut W, top: usize) -> io::Result<()> {
+        writeln!(out, "{} turns, {} distinct numbers", self.game.turn(), self.distinct())?;
+        let next = self.game.upcoming();
+        match self.game.ledger().get(next) {
+            0 => writeln!(out, "Next spoken: {}, for the first time", next)?,
+            last => writeln!(out, "Next spoken: {}, last on turn {}", next, last)?
+        }
+        if let Some(occurrences) = self.occurrences(next) {
+            writeln!(out, "  spoken {} times before, first on turn {}", occurrences.count, occurrences.first)?;
+        }
+        writeln!(out, "Most frequent below {}:", self.tracked)?;
+        for (number, occurrences) in self.by_frequency().into_iter().take(top) {
+            writeln!(out, "{:>10} spoken {:>8} times, first on turn {}", number, occurrences.count, occurrences.first)?;
+        }
+        if let Some(gap) = self.longest_gap() {
+            writeln!(out, "Longest gap: {} turns, {} between turns {} and {}", gap.to - gap.from, gap.number, gap.from, gap.to)?;
+        }
+        writeln!(out, "Proportion of zeros:")?;
+        for (turn, proportion) in self.zero_proportion() {
+            writeln!(out, "{:>10} {:.6}", turn, proportion)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn solve(input: &str, turns: u32) {
+    match parse(input) {
+        Ok(starting) => match spoken_on(&starting, turns) {
+            Some(number) => println!("Turn {}: {}", turns, number),
+            None => println!("Cannot play {} turns", turns)
+        },
+        Err(e) => println!("Invalid starting numbers: {}", e)
+    }
+}
+
+pub fn query(args: &[String]) {
+    let usage = "usage: day15 [[--start <n,n,...>] <turns> [<checkpoint every> <checkpoint file>]]";
+    if args.is_empty() {
+        solve(INPUT, 2020);
+        solve(INPUT, 30_000_000);
+        return
+    }
+    let (starting, args) = match args {
+        [flag, numbers, rest @ ..] if flag == "--start" => (numbers.as_str(), rest),
+        _ => (INPUT, args)
+    };
+    let starting = match parse(starting) {
+        Ok(starting) => starting,
+        Err(e) => {
+            println!("Invalid starting numbers: {}", e);
+            return
+        }
+    };
+    let numbers = args.iter().take(2).map(|a| u32::from_str(a).ok()).collect::<Option<Vec<_>>>();
+    let (turns, checkpoint) = match (numbers.as_deref(), args.get(2)) {
+        (Some([turns]), None) if args.len() == 1 => (*turns, None),
+        (Some([turns, every]), Some(file)) if args.len() == 3 => (*turns, Some((*every, Path::new(file)))),
+        _ => {
+            println!("{}", usage);
+            return
+        }
+    };
+    let mut analysis = match checkpoint {
+        Some((_, file)) if file.exists() => match Analysis::resume(file) {
+            Ok(analysis) if analysis.game().starting() != starting.as_slice() => {
+                println!("{} continues a game starting with {:?}", file.display(), analysis.game().starting());
+                return
+            }
+            Ok(analysis) => {
+                println!("Resuming from turn {}", analysis.game().turn());
+                analysis
+            }
+            Err(e) => {
+                println!("Cannot resume from {}: {}", file.display(), e);
+                return
+            }
+        },
+        _ => Analysis::new(MemoryGame::new(starting), (turns / 20).max(1))
+    };
+
+    let result = analysis.run(turns, checkpoint)
+        .and_then(|_| checkpoint.map_or(Ok(()), |(_, file)| analysis.save(file)))
+        .and_then(|_| analysis.report(&mut io::stdout(), 10));
+    if let Err(e) = result {
+        println!("Analysis failed: {}", e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day15::*;
+
+    #[test]
+    fn example() {
+        let starting = parse(EXAMPLE_INPUT).unwrap();
+        assert_eq!(MemoryGame::new(starting.clone()).take(10).collect::<Vec<_>>(), vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
+        assert_eq!(spoken_on(&starting, 2020), Some(436));
+        assert_eq!(spoken_on(&[1, 3, 2], 2020), Some(1));
+        assert_eq!(spoken_on(&starting, 0), None);
+    }
+
+    #[test]
+    fn sparse_region() {
+        let starting = parse(INPUT).unwrap();
+        let dense = MemoryGame::with_dense_limit(starting.clone(), u32::MAX);
+        let split = MemoryGame::with_dense_limit(starting, 16);
+        assert!(dense.zip(split).take(100_000).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn statistics() {
+        let mut analysis = Analysis::new(MemoryGame::new(parse(EXAMPLE_INPUT).unwrap()), 5);
+        analysis.run(10, None).unwrap();
+        // 0, 3, 6, 0, 3, 3, 1, 0, 4, 0
+        assert_eq!(analysis.occurrences(0), Some(Occurrences { first: 1, count: 4 }));
+        assert_eq!(analysis.occurrences(4), Some(Occurrences { first: 9, count: 1 }));
+        assert_eq!(analysis.occurrences(2), None);
+        assert_eq!(analysis.by_frequency()[1], (3, Occurrences { first: 2, count: 3 }));
+        assert_eq!(analysis.longest_gap(), Some(Gap { number: 0, from: 4, to: 8 }));
+        assert_eq!(analysis.zero_proportion(), &[(5, 0.4), (10, 0.4)]);
+        assert_eq!(analysis.distinct(), 5);
+
+        let mut bounded = Analysis::with_tracking(MemoryGame::new(parse(EXAMPLE_INPUT).unwrap()), 5, 4);
+        bounded.run(10, None).unwrap();
+        assert_eq!(bounded.occurrences(3), Some(Occurrences { first: 2, count: 3 }));
+        assert_eq!(bounded.occurrences(6), None);
+        assert_eq!(bounded.by_frequency().len(), 3);
+        assert_eq!(bounded.distinct(), 5);
+        assert_eq!(bounded.longest_gap(), analysis.longest_gap());
+    }
+
+    #[test]
+    fn checkpoints() {
+        let mut straight = Analysis::new(MemoryGame::with_dense_limit(parse(INPUT).unwrap(), 100), 1000);
+        straight.run(20_000, None).unwrap();
+
+        let mut first = Analysis::new(MemoryGame::with_dense_limit(parse(INPUT).unwrap(), 100), 1000);
+        first.run(7_777, None).unwrap();
+        let mut checkpoint = Vec::new();
+        first.write_checkpoint(&mut checkpoint).unwrap();
+        let mut resumed = Analysis::read_checkpoint(checkpoint.as_slice()).unwrap();
+        resumed.run(20_000, None).unwrap();
+
+        assert_eq!(resumed.game().upcoming(), straight.game().upcoming());
+        assert_eq!(resumed.by_frequency(), straight.by_frequency());
+        assert_eq!(resumed.longest_gap(), straight.longest_gap());
+        assert_eq!(resumed.zero_proportion(), straight.zero_proportion());
+        assert_eq!(resumed.distinct(), straight.distinct());
+        assert!(Analysis::read_checkpoint("state 1 x 3".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn checkpoint_files() {
+        let directory = std::env::temp_dir().join(format!("day15-checkpoints-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        // the temporary file must not be the checkpoint itself
+        let file = directory.join("run.tmp");
 
-        ledger_vec[previous_number] = current_index as i32;
-        numbers.push(number_to_say);
+        let mut analysis = Analysis::new(MemoryGame::new(parse(EXAMPLE_INPUT).unwrap()), 100);
+        analysis.run(1000, Some((300, &file))).unwrap();
+        let resumed = Analysis::resume(&file).unwrap();
+        assert_eq!(resumed.game().turn(), 900);
+        assert_eq!(resumed.game().starting(), &[0, 3, 6]);
+        assert!(!directory.join("run.tmp.partial").exists());
+        fs::remove_dir_all(&directory).unwrap();
     }
-    println!("{:?} with max", numbers.last());
 }
 
+#[cfg(test)]
 pub const EXAMPLE_INPUT: &str = "0,3,6";
 pub const INPUT: &str = "9,6,0,10,18,2,1";
\ No newline at end of file
@@ -818,6 +818,7 @@ mod test {
     }
 }
 
+#[allow(dead_code)]
 pub const EXAMPLE_INPUT: &str = "Tile 2311:
 ..##.#..#.
 ##..#.....
@@ -5,10 +5,11 @@ use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::combinator::{eof, map};
 use nom::IResult;
-use nom::lib::std::collections::HashSet;
 use nom::multi::many1;
 use nom::sequence::terminated;
 
+use crate::automaton::{Automaton, Hex, Rule};
+
 #[derive(Debug, Copy, Clone, Default, Hash, Eq, PartialEq)]
 struct HexTile { x: i32, y: i32, z: i32 }
 
@@ -20,19 +21,6 @@ impl Add for HexTile {
     }
 }
 
-impl HexTile {
-    fn neighbourhood(self) -> [Self; 6] {
-        [
-            self + Direction::NorthEast.offset(),
-            self + Direction::NorthWest.offset(),
-            self + Direction::West.offset(),
-            self + Direction::SouthWest.offset(),
-            self + Direction::SouthEast.offset(),
-            self + Direction::East.offset()
-        ]
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 enum Direction {
     NorthEast,
@@ -87,12 +75,9 @@ fn direction(input: &str) -> IResult<&str, Direction> {
     ))(input)
 }
 
-fn count_active(map: &HashMap<HexTile, bool>) -> usize {
-    map.values().filter(|v| **v).count()
-}
-
-pub fn solve() {
-    let mut rest = INPUT;
+// black tiles after following every path from the reference tile, flipping the tile it ends on
+fn initial_floor(input: &str) -> Vec<[i32; 3]> {
+    let mut rest = input;
     let mut black = HashMap::<HexTile, bool>::new();
     while let Ok((rest2, path)) = path(rest) {
         rest = rest2;
@@ -101,38 +86,57 @@ pub fn solve() {
         *flag = !*flag;
     }
 
-    println!("Initially active: {}", count_active(&black));
+    black.iter().filter(|(_, v)| **v).map(|(HexTile { x, y, z }, _)| [*x, *y, *z]).collect()
+}
+
+fn black_after(initial: Vec<[i32; 3]>, days: usize) -> usize {
+    let mut floor = Automaton::new(Hex, Rule::new(&[2], &[1, 2]), initial);
+    floor.run(days);
+    floor.alive().len()
+}
 
-    for day in 0..100 {
-        let to_consider = black.iter()
-            .filter_map(|(k, v)| if *v { Some(k) } else { None })
-            .flat_map(|t|{
-                let [n1, n2, n3, n4, n5, n6] = t.neighbourhood();
-                vec![*t, n1, n2, n3, n4, n5, n6].into_iter()
-            })
-            .collect::<HashSet<_>>();
+pub fn solve() {
+    let initial = initial_floor(INPUT);
+    println!("Initially active: {}", initial.len());
+    println!("Day 100: {}", black_after(initial, 100))
+}
 
-        black = to_consider.iter()
-            .map(|tile|{
-                let black_neighbours = tile.neighbourhood().iter()
-                    .filter(|neighbour|{
-                        *black.get(neighbour).unwrap_or(&false)
-                    })
-                    .count();
-                let is_black = *black.get(tile).unwrap_or(&false);
-                let becomes_black = if is_black {
-                    black_neighbours == 1 || black_neighbours == 2
-                } else {
-                    black_neighbours == 2
-                };
+#[cfg(test)]
+mod test {
+    use crate::day24::*;
 
-                (*tile, becomes_black)
-            })
-            .collect::<HashMap<_,_>>();
+    #[test]
+    fn example() {
+        let initial = initial_floor(EXAMPLE_INPUT);
+        assert_eq!(initial.len(), 10);
+        assert_eq!(black_after(initial.clone(), 1), 15);
+        assert_eq!(black_after(initial.clone(), 10), 37);
+        assert_eq!(black_after(initial, 100), 2208);
     }
-    println!("Day 100: {}", count_active(&black))
 }
 
+#[cfg(test)]
+const EXAMPLE_INPUT: &str = "sesenwnenenewseeswwswswwnenewsewsw
+neeenesenwnwwswnenewnwwsewnenwseswesw
+seswneswswsenwwnwse
+nwnwneseeswswnenewneswwnewseswneseene
+swweswneswnenwsewnwneneseenw
+eesenwseswswnenwswnwnwsewwnwsene
+sewnenenenesenwsewnenwwwse
+wenwwweseeeweswwwnwwe
+wsweesenenewnwwnwsenewsenwwsesesenwne
+neeswseenwwswnwswswnw
+nenwswwsewswnenenewsenwsenwnesesenew
+enewnwewneswsewnwswenweswnenwsenwsw
+sweneswneswneneenwnewenewwneswswnese
+swwesenesewenwneswnwwneseswwne
+enesenwswwswneneswsenwnewswseenwsese
+wnwnesenesenenwwnenwsewesewsesesew
+nenewswnwewswnenesenwnesewesw
+eneswnwswnwsenenwnwnwwseeswneewsenese
+neswnwewnwnwseenwseesewsenwsweewe
+wseweeenwnesenwwwswnew";
+
 const INPUT: &str = "swswswswseswswseseswswnwsesw
 nwewsewneswenwesenesenw
 neeeenwseseewwnenenesweeeeene
@@ -1,17 +1,27 @@
 use std::time::Instant;
-use mod_exp::mod_exp;
+
+use crate::modular::Modular;
 
 const MODULUS: usize = 20201227;
+const SUBJECT: usize = 7;
+
+type Key = Modular<{ MODULUS as i64 }>;
 
-fn disc_log_table() -> Box<[u32; MODULUS]> { // brute force to the max!
-    let mut array = box [0u32;MODULUS]; // finally, an excuse to break out this syntax
+// loop size for every public key, brute force to the max!
+fn disc_log_table() -> Vec<u32> {
+    let mut table = vec![0u32; MODULUS];
     let mut curr = 1;
     for i in 1..MODULUS {
-        curr = (curr * 7) % MODULUS;
-        array[curr] = i as u32;
+        curr = (curr * SUBJECT) % MODULUS;
+        table[curr] = i as u32;
     }
 
-    array
+    table
+}
+
+fn encryption_key(table: &[u32], pk_card: u32, pk_door: u32) -> i64 {
+    let loop_size_card = table[pk_card as usize];
+    Key::new(pk_door).pow(loop_size_card as u64).value()
 }
 
 pub fn solve() {
@@ -20,9 +30,21 @@ pub fn solve() {
     println!("Building table took {:?}", Instant::now() - start);
     let pk_card = 2069194u32;
     let pk_door = 16426071u32;
-    let loop_size_card = table[pk_card as usize];
-    let loop_size_door = table[pk_door as usize];
-    println!("Loop size card: {}", loop_size_card);
-    println!("Loop size door: {}", loop_size_door);
-    println!("Encryption key: {}", mod_exp(pk_door as u64, loop_size_card as u64, MODULUS as u64));
-}
\ No newline at end of file
+    println!("Loop size card: {}", table[pk_card as usize]);
+    println!("Loop size door: {}", table[pk_door as usize]);
+    println!("Encryption key: {}", encryption_key(&table, pk_card, pk_door));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day25::*;
+
+    #[test]
+    fn example() {
+        let table = disc_log_table();
+        assert_eq!(table[5764801], 8);
+        assert_eq!(table[17807724], 11);
+        assert_eq!(encryption_key(&table, 5764801, 17807724), 14897079);
+        assert_eq!(encryption_key(&table, 17807724, 5764801), 14897079);
+    }
+}
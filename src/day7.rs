@@ -1,57 +1,542 @@
+use num::{BigUint, Zero};
 use regex::Regex;
-use std::collections::{HashSet, HashMap};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{ErrorKind, Write};
+
+use crate::cli;
+use crate::graph::{Graph, NodeId};
 
 #[derive(Debug)]
 struct Bag<'a> {
     name: &'a str,
-    contents: HashMap<&'a str, u32>,
+    // in the order the rule lists them
+    contents: Vec<(&'a str, u64)>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RuleError<'a> {
+    Cycle(Vec<&'a str>),
+    Dangling { container: &'a str, missing: &'a str },
+    Duplicate { name: &'a str, definitions: usize },
+    Malformed { line: usize, text: &'a str },
+    InvalidCount { line: usize, count: &'a str },
+    RepeatedContent { line: usize, bag: &'a str },
+}
+
+impl Display for RuleError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleError::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleError::Dangling { container, missing } => write!(f, "{} contains undefined bag {}", container, missing),
+            RuleError::Duplicate { name, definitions } => write!(f, "{} is defined {} times", name, definitions),
+            RuleError::Malformed { line, text } => write!(f, "line {}: cannot parse '{}'", line, text),
+            RuleError::InvalidCount { line, count } => write!(f, "line {}: {} is not a valid bag count", line, count),
+            RuleError::RepeatedContent { line, bag } => write!(f, "line {}: {} bags are listed more than once", line, bag),
+        }
+    }
+}
+
+// restricts an export to the bags inside, or the bags around the given one
+#[derive(Debug, Copy, Clone)]
+pub enum Focus<'a> {
+    Contents(&'a str),
+    Containers(&'a str),
+}
+
+// validated rule set, answering questions about any bag. Answers are memoised across queries
+pub struct BagRules<'a> {
+    graph: Graph<&'a str, u64>,
+    contents_memo: HashMap<NodeId, BigUint>,
+    // indexed by node, computed on first use
+    depths: Option<Vec<usize>>,
+}
+
+impl<'a> BagRules<'a> {
+    pub fn new(input: &'a str) -> Result<Self, Vec<RuleError<'a>>> {
+        let (rules, malformed) = parse(input);
+        if !malformed.is_empty() {
+            return Err(malformed);
+        }
+        let problems = validate(&rules);
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        Ok(BagRules { graph: graph(&rules), contents_memo: HashMap::new(), depths: None })
+    }
+
+    // every bag that can eventually contain the given one
+    pub fn containers(&self, bag: &str) -> Option<Vec<&'a str>> {
+        let id = self.graph.id(&bag)?;
+        let mut containers = self.graph.ancestors(id).into_iter().map(|n| *self.graph.label(n)).collect::<Vec<_>>();
+        containers.sort();
+        Some(containers)
+    }
+
+    // number of bags inside the given one, not counting itself. Nesting multiplies quickly, so this is unbounded
+    pub fn contents_count(&mut self, bag: &str) -> Option<BigUint> {
+        let id = self.graph.id(&bag)?;
+        let count = self.graph.path_sum(id, &mut self.contents_memo).expect("validated rule sets are acyclic");
+        Some(count - 1u32)
+    }
+
+    // how many of each kind of bag end up inside the given one
+    pub fn bill_of_materials(&self, bag: &str) -> Option<Vec<(&'a str, BigUint)>> {
+        let id = self.graph.id(&bag)?;
+        let mut quantities = vec![BigUint::zero(); self.graph.len()];
+        quantities[id.index()] = 1u32.into();
+        for node in self.graph.topological_order().unwrap() {
+            if quantities[node.index()].is_zero() {
+                continue
+            }
+            for (child, count) in self.graph.successors(node) {
+                let added = &quantities[node.index()] * *count;
+                quantities[child.index()] += added;
+            }
+        }
+        quantities[id.index()] = BigUint::zero();
+
+        let mut materials = self.graph.nodes()
+            .filter(|n| !quantities[n.index()].is_zero())
+            .map(|n| (*self.graph.label(n), quantities[n.index()].clone()))
+            .collect::<Vec<_>>();
+        materials.sort();
+        Some(materials)
+    }
+
+    // levels of bags nested inside the given one, 0 if it is always empty
+    pub fn max_depth(&mut self, bag: &str) -> Option<usize> {
+        let id = self.graph.id(&bag)?;
+        let graph = &self.graph;
+        let depths = self.depths.get_or_insert_with(|| {
+            // children come later in topological order, so walking it backwards sees them first
+            let mut depths = vec![0; graph.len()];
+            for node in graph.topological_order().unwrap().into_iter().rev() {
+                depths[node.index()] = graph.successors(node).iter().map(|(c, _)| depths[c.index()] + 1).max().unwrap_or(0);
+            }
+            depths
+        });
+        Some(depths[id.index()])
+    }
 }
 
 pub fn solve(input: &str) {
-    let bags = parse(input);
-    println!("Possible containers: {}", containers(&bags, "shiny gold", &mut HashSet::new()));
-    println!("Contents-sum: {}", contents_sum(&bags, "shiny gold", &mut HashMap::new()) - 1);
+    let mut rules = match BagRules::new(input) {
+        Ok(rules) => rules,
+        Err(problems) => {
+            for problem in &problems {
+                println!("Invalid rule set, {}", problem);
+            }
+            return
+        }
+    };
+
+    println!("Possible containers: {}", rules.containers("shiny gold").unwrap().len());
+    println!("Contents-sum: {}", rules.contents_count("shiny gold").unwrap());
+}
+
+// command line access, see usage
+pub fn query(args: &[String]) {
+    let usage = "usage: day7 [<containers|contents|materials|depth> <bag> [rules file]]
+       day7 dot [<bag>] [--containers] [--rules <file>] [--output <file>]";
+    if args.is_empty() {
+        solve(INPUT);
+        return
+    }
+    if args.first().map(String::as_str) == Some("dot") {
+        dot(&args[1..], usage);
+        return
+    }
+    if args.len() < 2 || args.len() > 3 {
+        println!("{}", usage);
+        return
+    }
+    let input = match cli::input_or(args.get(2), INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    let mut rules = match BagRules::new(&input) {
+        Ok(rules) => rules,
+        Err(problems) => {
+            for problem in &problems {
+                println!("Invalid rule set, {}", problem);
+            }
+            return
+        }
+    };
+
+    let bag = args[1].as_str();
+    let answered = match args[0].as_str() {
+        "containers" => rules.containers(bag).map(|containers| {
+            println!("{} bags can contain {}", containers.len(), bag);
+            for container in containers {
+                println!("  {}", container)
+            }
+        }),
+        "contents" => rules.contents_count(bag).map(|count| println!("{} contains {} bags", bag, count)),
+        "materials" => rules.bill_of_materials(bag).map(|materials| {
+            for (name, count) in materials {
+                println!("{:>8} {}", count, name)
+            }
+        }),
+        "depth" => rules.max_depth(bag).map(|depth| println!("{} nests {} levels deep", bag, depth)),
+        _ => {
+            println!("{}", usage);
+            Some(())
+        }
+    };
+    if answered.is_none() {
+        println!("No bag named {}", bag)
+    }
 }
 
-fn parse(input: &str) -> HashMap<&str, Bag> {
-    let line = Regex::new("(?m)^([a-z ]+) bags contain (.+)$").unwrap();
-    let spec = Regex::new(r"(\d+) ([a-z ]+) bags?[,.]").unwrap();
-    line.captures_iter(input).map(|phrase|{
-        let name = phrase.get(1).unwrap().as_str();
-
-        (name, Bag {
-            name,
-            contents: spec.captures_iter(phrase.get(2).unwrap().as_str()).map(|contents|
-              (contents.get(2).unwrap().as_str(), contents.get(1).unwrap().as_str().parse().unwrap())
-            ).collect(),
-        })
-    }).collect()
+// graphviz export of the rules, the puzzle input unless a rules file is given. Written to stdout
+// unless an output file is given
+fn dot(args: &[String], usage: &str) {
+    let mut bag = None;
+    let mut containers = false;
+    let mut rules = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--containers" => containers = true,
+            option @ "--rules" | option @ "--output" => match args.next() {
+                Some(file) if option == "--rules" => rules = Some(file),
+                Some(file) => output = Some(file),
+                None => {
+                    println!("{}", usage);
+                    return
+                }
+            },
+            name if bag.is_none() && !name.starts_with("--") => bag = Some(name),
+            _ => {
+                println!("{}", usage);
+                return
+            }
+        }
+    }
+    let focus = match (bag, containers) {
+        (Some(bag), false) => Some(Focus::Contents(bag)),
+        (Some(bag), true) => Some(Focus::Containers(bag)),
+        (None, false) => None,
+        (None, true) => {
+            println!("{}", usage);
+            return
+        }
+    };
+
+    let input = match cli::input_or(rules, INPUT) {
+        Some(input) => input,
+        None => return
+    };
+    let written = match output {
+        Some(file) => std::fs::File::create(file).and_then(|mut out| export_dot(&input, focus, &mut out)),
+        None => export_dot(&input, focus, &mut io::stdout())
+    };
+    if let Err(e) = written {
+        println!("Cannot export graph: {}", e)
+    }
+}
+
+// every line that is not a well-formed rule is reported, the others are kept
+fn parse(input: &str) -> (Vec<Bag<'_>>, Vec<RuleError<'_>>) {
+    let rule = Regex::new(r"^([a-z ]+) bags contain (.+)\.$").unwrap();
+    let spec = Regex::new(r"^(\d+) ([a-z ]+) bags?$").unwrap();
+    let mut bags = Vec::new();
+    let mut problems = Vec::new();
+
+    for (index, text) in input.lines().enumerate() {
+        let line = index + 1;
+        let text = text.trim();
+        if text.is_empty() {
+            continue
+        }
+        let phrase = match rule.captures(text) {
+            Some(phrase) => phrase,
+            None => {
+                problems.push(RuleError::Malformed { line, text });
+                continue
+            }
+        };
+
+        let mut contents = Vec::new();
+        let listed = phrase.get(2).unwrap().as_str();
+        if listed != "no other bags" {
+            for clause in listed.split(", ") {
+                match spec.captures(clause) {
+                    Some(c) => match c.get(1).unwrap().as_str().parse() {
+                        Ok(count) => {
+                            let bag = c.get(2).unwrap().as_str();
+                            if contents.iter().any(|(listed, _)| *listed == bag) {
+                                problems.push(RuleError::RepeatedContent { line, bag })
+                            } else {
+                                contents.push((bag, count))
+                            }
+                        }
+                        Err(_) => problems.push(RuleError::InvalidCount { line, count: c.get(1).unwrap().as_str() })
+                    },
+                    None => problems.push(RuleError::Malformed { line, text: clause })
+                }
+            }
+        }
+        bags.push(Bag { name: phrase.get(1).unwrap().as_str(), contents })
+    }
+
+    (bags, problems)
 }
 
-fn containers<'a>(bags: &HashMap<&'a str, Bag<'a>>, next: &'a str, seen: &mut HashSet<&'a str>) -> usize {
-    for bag in bags.values() { // O(n^2), unfortunately
-        if !seen.contains(bag.name) && bag.contents.contains_key(next) {
-            seen.insert(bag.name);
-            containers(bags, bag.name, seen);
+fn validate<'a>(rules: &[Bag<'a>]) -> Vec<RuleError<'a>> {
+    let mut problems = Vec::new();
+    let mut definitions = HashMap::<&str, usize>::new();
+    for bag in rules {
+        *definitions.entry(bag.name).or_default() += 1;
+    }
+
+    for bag in rules {
+        match definitions[bag.name] {
+            1 => (),
+            0 => continue, // already reported
+            n => {
+                problems.push(RuleError::Duplicate { name: bag.name, definitions: n });
+                definitions.insert(bag.name, 0);
+            }
         }
     }
 
-    seen.len()
+    for bag in rules {
+        for (child, _) in &bag.contents {
+            if !definitions.contains_key(child) {
+                problems.push(RuleError::Dangling { container: bag.name, missing: child })
+            }
+        }
+    }
+
+    let graph = graph(rules);
+    for cycle in graph.cycles() {
+        problems.push(RuleError::Cycle(cycle.into_iter().map(|n| *graph.label(n)).collect()))
+    }
+
+    problems
 }
 
-fn contents_sum<'a>(bags: &HashMap<&'a str, Bag<'a>>, name: &'a str, memo: &mut HashMap<&'a str, u32>) -> u32 {
-    let mut sum = 1; // O(n), unless I am mistaken
-    let content = &bags[name].contents;
-    for (child, count) in content {
-        let previous = memo.get(child).map(u32::clone);
-        let actual = previous.unwrap_or(contents_sum(bags, child, memo));
-        sum += count * actual
+pub fn export_dot<O: Write>(input: &str, focus: Option<Focus>, out: &mut O) -> io::Result<()> {
+    let (rules, malformed) = parse(input);
+    if let Some(problem) = malformed.first() {
+        return Err(io::Error::new(ErrorKind::InvalidData, problem.to_string()));
+    }
+    let graph = graph(&rules);
+    let find = |name| graph.id(&name)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("No bag named {}", name)));
+
+    let included = match focus {
+        None => graph.nodes().collect(),
+        Some(Focus::Contents(name)) => {
+            let root = find(name)?;
+            let mut nodes = graph.descendants(root);
+            nodes.insert(root);
+            nodes
+        }
+        Some(Focus::Containers(name)) => {
+            let root = find(name)?;
+            let mut nodes = graph.ancestors(root);
+            nodes.insert(root);
+            nodes
+        }
+    };
+
+    graph.write_dot(out, |node| included.contains(&node))
+}
+
+fn graph<'a>(rules: &[Bag<'a>]) -> Graph<&'a str, u64> {
+    let mut graph = Graph::new();
+    for bag in rules {
+        let container = graph.node(bag.name);
+        for (child, count) in &bag.contents {
+            let child = graph.node(*child);
+            graph.add_edge(container, child, *count);
+        }
+    }
+    graph
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day7::*;
+
+    fn problems(input: &str) -> Vec<RuleError<'_>> {
+        match BagRules::new(input) {
+            Ok(_) => Vec::new(),
+            Err(problems) => problems
+        }
+    }
+
+    #[test]
+    fn valid_rules() {
+        assert!(problems(EXAMPLE_INPUT).is_empty());
+    }
+
+    fn count(n: u64) -> Option<BigUint> {
+        Some(n.into())
     }
 
-    memo.insert(name, sum);
+    #[test]
+    fn queries() {
+        let mut rules = BagRules::new(EXAMPLE_INPUT).unwrap();
+        assert_eq!(rules.containers("shiny gold"), Some(vec!["bright white", "dark orange", "light red", "muted yellow"]));
+        assert_eq!(rules.contents_count("shiny gold"), count(32));
+        let materials = [("dark olive", 1u32), ("dotted black", 16), ("faded blue", 13), ("vibrant plum", 2)];
+        assert_eq!(rules.bill_of_materials("shiny gold"), Some(materials.iter().map(|(n, c)| (*n, (*c).into())).collect()));
+        assert_eq!(rules.max_depth("shiny gold"), Some(2));
+        assert_eq!(rules.max_depth("faded blue"), Some(0));
+        assert_eq!(rules.contents_count("plaid purple"), None);
 
-    sum
+        let mut nested = BagRules::new(NESTED_EXAMPLE).unwrap();
+        assert_eq!(nested.contents_count("shiny gold"), count(126));
+        assert_eq!(nested.bill_of_materials("shiny gold").unwrap().last(), Some(&("dark yellow", 8u32.into())));
+        assert_eq!(nested.max_depth("shiny gold"), Some(6));
+    }
+
+    #[test]
+    fn large_counts() {
+        let input = "shiny gold bags contain 10000000 dark red bags.
+dark red bags contain 10000000 dark orange bags.
+dark orange bags contain 10000000 dark yellow bags.
+dark yellow bags contain 10000000 dark green bags.
+dark green bags contain no other bags.";
+        let mut rules = BagRules::new(input).unwrap();
+        // far beyond u64
+        let level = |n| BigUint::from(10_000_000u32).pow(n);
+        assert_eq!(rules.contents_count("shiny gold"), Some(level(1) + level(2) + level(3) + level(4)));
+        assert_eq!(rules.bill_of_materials("shiny gold").unwrap()[0], ("dark green", level(4)));
+    }
+
+    #[test]
+    fn duplicates_and_dangling_references() {
+        let input = "light red bags contain 1 bright white bag.
+light red bags contain 2 muted yellow bags.
+bright white bags contain no other bags.";
+        assert_eq!(problems(input), vec![
+            RuleError::Duplicate { name: "light red", definitions: 2 },
+            RuleError::Dangling { container: "light red", missing: "muted yellow" },
+        ]);
+    }
+
+    #[test]
+    fn cycles() {
+        let input = "light red bags contain 1 bright white bag.
+bright white bags contain 2 dark orange bags.
+dark orange bags contain 3 light red bags.";
+        assert_eq!(problems(input), vec![RuleError::Cycle(vec!["light red", "bright white", "dark orange", "light red"])]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let input = "light red bags contains 1 bright white bag.
+bright white bags contain 1 shiny gold bag, two muted yellow bags.
+shiny gold bags contain 99999999999999999999 dark olive bags.
+dark olive bags contain no other bags.";
+        assert_eq!(problems(input), vec![
+            RuleError::Malformed { line: 1, text: "light red bags contains 1 bright white bag." },
+            RuleError::Malformed { line: 2, text: "two muted yellow bags" },
+            RuleError::InvalidCount { line: 3, count: "99999999999999999999" },
+        ]);
+        assert_eq!(problems("light red bags contain 1 red bag, 2 blue bags, 2 red bags."),
+                   vec![RuleError::RepeatedContent { line: 1, bag: "red" }]);
+    }
+
+    // a distinct colour for every number
+    fn colour(n: usize) -> String {
+        let letters = n.to_string().bytes().map(|digit| (digit - b'0' + b'a') as char).collect::<String>();
+        format!("chain {}", letters)
+    }
+
+    fn chain(len: usize) -> String {
+        let mut input = (0..len - 1)
+            .map(|n| format!("{} bags contain 1 {} bag.\n", colour(n), colour(n + 1)))
+            .collect::<String>();
+        input.push_str(&format!("{} bags contain no other bags.", colour(len - 1)));
+        input
+    }
+
+    #[test]
+    fn long_chains() {
+        let input = chain(100_000);
+        let mut rules = BagRules::new(&input).unwrap();
+        assert_eq!(rules.contents_count(&colour(0)), count(99_999));
+        assert_eq!(rules.max_depth(&colour(0)), Some(99_999));
+
+        let last = format!("{} bags contain no other bags.", colour(99_999));
+        let closed = input.replace(&last, &format!("{} bags contain 1 {} bag.", colour(99_999), colour(0)));
+        assert_eq!(problems(&closed).len(), 1);
+    }
+
+    fn dot(focus: Option<Focus>) -> String {
+        let input = "light red bags contain 2 bright white bags.
+bright white bags contain 3 shiny gold bags, 4 faded blue bags.
+shiny gold bags contain no other bags.
+dark orange bags contain 1 bright white bag.
+faded blue bags contain no other bags.";
+        let mut out = Vec::new();
+        export_dot(input, focus, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dot_export() {
+        // nodes and edges follow the order of the rules
+        assert_eq!(dot(None), r#"digraph {
+    "light red";
+    "bright white";
+    "shiny gold";
+    "faded blue";
+    "dark orange";
+    "light red" -> "bright white" [label="2"];
+    "bright white" -> "shiny gold" [label="3"];
+    "bright white" -> "faded blue" [label="4"];
+    "dark orange" -> "bright white" [label="1"];
 }
+"#);
+        assert_eq!(dot(Some(Focus::Contents("bright white"))), r#"digraph {
+    "bright white";
+    "shiny gold";
+    "faded blue";
+    "bright white" -> "shiny gold" [label="3"];
+    "bright white" -> "faded blue" [label="4"];
+}
+"#);
+        assert_eq!(dot(Some(Focus::Containers("bright white"))), r#"digraph {
+    "light red";
+    "bright white";
+    "dark orange";
+    "light red" -> "bright white" [label="2"];
+    "dark orange" -> "bright white" [label="1"];
+}
+"#);
+    }
+}
+
+#[cfg(test)]
+pub const NESTED_EXAMPLE: &str = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 2 dark orange bags.
+dark orange bags contain 2 dark yellow bags.
+dark yellow bags contain 2 dark green bags.
+dark green bags contain 2 dark blue bags.
+dark blue bags contain 2 dark violet bags.
+dark violet bags contain no other bags.";
+
+#[cfg(test)]
+pub const EXAMPLE_INPUT: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
 
 pub const INPUT: &str = "dull blue bags contain 2 dotted green bags, 1 dull brown bag, 3 striped tomato bags, 5 muted blue bags.
 dotted cyan bags contain 2 faded lavender bags, 1 drab fuchsia bag, 5 bright blue bags.
@@ -1,46 +1,288 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::BufRead;
+use std::iter;
+use std::ops::Range;
 use std::str::FromStr;
 
-fn valid(target: usize, prefixes: &[usize])->bool {
-    for x1 in prefixes {
-        for x2 in prefixes {
-            if x1 + x2 == target {
-                return true
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Invalid {
+    pub position: usize,
+    pub value: i64,
+}
+
+// Checks a stream of numbers against the sums of distinct pairs among the previous `preamble` ones.
+// The pair sums are kept as a multiset, so a check is a single lookup and sliding the window costs
+// one pass over it. Sums are widened, any two i64 may be added
+pub struct Validator<I> {
+    source: I,
+    preamble: usize,
+    window: VecDeque<i64>,
+    pair_sums: HashMap<i128, usize>,
+    position: usize,
+}
+
+impl<I> Validator<I> where I: Iterator<Item=i64> {
+    pub fn new(source: I, preamble: usize) -> Self {
+        Validator { source, preamble, window: VecDeque::with_capacity(preamble + 1), pair_sums: HashMap::new(), position: 0 }
+    }
+
+    fn is_pair_sum(&self, value: i64) -> bool {
+        // sums whose count drops to zero are removed
+        self.pair_sums.contains_key(&(value as i128))
+    }
+
+    // checks the next number of the stream, then moves the window past it
+    pub fn check(&mut self, value: i64) -> Option<Invalid> {
+        let position = self.position;
+        let invalid = position >= self.preamble && !self.is_pair_sum(value);
+        self.position += 1;
+        self.push(value);
+        if invalid {
+            Some(Invalid { position, value })
+        } else {
+            None
+        }
+    }
+
+    fn push(&mut self, value: i64) {
+        for previous in &self.window {
+            *self.pair_sums.entry(*previous as i128 + value as i128).or_default() += 1;
+        }
+        self.window.push_back(value);
+
+        if self.window.len() > self.preamble {
+            let evicted = self.window.pop_front().unwrap();
+            for remaining in &self.window {
+                let sum = evicted as i128 + *remaining as i128;
+                let count = self.pair_sums.get_mut(&sum).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    self.pair_sums.remove(&sum);
+                }
+            }
+        }
+    }
+}
+
+impl<I> Iterator for Validator<I> where I: Iterator<Item=i64> {
+    type Item = Invalid;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(value) = self.source.next() {
+            if let Some(invalid) = self.check(value) {
+                return Some(invalid);
             }
         }
+
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    InvalidNumber { line: usize, text: String },
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "cannot read input: {}", e),
+            ReadError::InvalidNumber { line, text } => write!(f, "line {}: '{}' is not a number", line, text),
+        }
     }
+}
 
-    return false
+// whitespace separated numbers, problems are passed on instead of ending the stream
+pub fn read_numbers<R: BufRead>(reader: R) -> impl Iterator<Item=Result<i64, ReadError>> {
+    reader.lines().enumerate().flat_map(|(index, line)| match line {
+        Ok(line) => line
+            .split_ascii_whitespace()
+            .map(|n| i64::from_str(n).map_err(|_| ReadError::InvalidNumber { line: index + 1, text: n.to_owned() }))
+            .collect::<Vec<_>>(),
+        Err(e) => vec![Err(ReadError::Io(e))]
+    })
+}
+
+// the invalid numbers read from any source, with read problems passed on in between
+pub fn validate<R: BufRead>(reader: R, preamble: usize) -> impl Iterator<Item=Result<Invalid, ReadError>> {
+    let mut validator = Validator::new(iter::empty(), preamble);
+    read_numbers(reader).filter_map(move |number| match number {
+        Ok(value) => validator.check(value).map(Ok),
+        Err(e) => Some(Err(e))
+    })
+}
+
+// every range of at least min_len numbers adding up to target, ordered by start. Empty if there are none.
+// Prefix sums of i64 fit an i128 for any realistic input length
+pub fn contiguous_ranges(numbers: &[i64], target: i64, min_len: usize) -> Vec<Range<usize>> {
+    let mut prefix_ends = HashMap::<i128, Vec<usize>>::new();
+    let mut ranges = Vec::new();
+    let mut prefix = 0i128;
+    prefix_ends.entry(prefix).or_default().push(0);
+
+    for (i, number) in numbers.iter().enumerate() {
+        prefix += *number as i128;
+        let end = i + 1;
+        if let Some(starts) = prefix_ends.get(&(prefix - target as i128)) {
+            ranges.extend(starts.iter().filter(|start| end - **start >= min_len).map(|start| *start..end));
+        }
+        prefix_ends.entry(prefix).or_default().push(end);
+    }
+
+    ranges.sort_by_key(|r| (r.start, r.end));
+    ranges
 }
 
 pub fn solve(input: &str, n: usize) {
-    let mut target = 0usize;
-    let numbers: Vec<usize> = input.split_ascii_whitespace().map(|n|usize::from_str(n).unwrap()).collect();
-    for i in n..numbers.len(){
-        let prefix = &numbers[i - n..i];
-        if !valid(numbers[i], prefix) {
-            target = numbers[i];
-            break
+    let mut target = None;
+    for checked in validate(input.as_bytes(), n) {
+        match checked {
+            Ok(Invalid { position, value }) => {
+                println!("Invalid: {} at position {}", value, position);
+                target = target.or(Some(value));
+            }
+            Err(e) => {
+                println!("Invalid input, {}", e);
+                return
+            }
         }
     }
+    let target = match target {
+        Some(target) => target,
+        None => {
+            println!("All numbers are valid");
+            return
+        }
+    };
 
-    let mut start = 0;
-    let mut end = 0;
-    let mut sum = 0;
+    // the ranges may lie anywhere, so they need all numbers at once
+    let numbers = read_numbers(input.as_bytes()).collect::<Result<Vec<_>, _>>().expect("validated above");
 
-    while end < numbers.len() && sum != target {
-        if sum < target {
-            sum += numbers[end];
-            end += 1;
-        } else {
-            sum -= numbers[start];
-            start += 1
+    let ranges = contiguous_ranges(&numbers, target, 2);
+    if ranges.is_empty() {
+        println!("No contiguous range sums to {}", target)
+    }
+    for range in ranges {
+        let slice = &numbers[range.clone()];
+        println!("Solution for {:?}: {}", range, slice.iter().min().unwrap() + slice.iter().max().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day9::*;
+
+    fn invalid(numbers: &[i64], preamble: usize) -> Vec<(usize, i64)> {
+        Validator::new(numbers.iter().copied(), preamble).map(|i| (i.position, i.value)).collect()
+    }
+
+    #[test]
+    fn example() {
+        let numbers = read_numbers(EXAMPLE_INPUT.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(invalid(&numbers, 5), vec![(14, 127)]);
+        assert_eq!(contiguous_ranges(&numbers, 127, 2), vec![2..6]);
+    }
+
+    #[test]
+    fn pairs_use_different_numbers() {
+        // 10 would need 5 twice
+        assert_eq!(invalid(&[5, 3, 10], 2), vec![(2, 10)]);
+        assert_eq!(invalid(&[5, 5, 10], 2), vec![]);
+        assert_eq!(invalid(&[5, 3, 5, 10], 3), vec![]);
+    }
+
+    #[test]
+    fn reports_every_invalid_number() {
+        assert_eq!(invalid(&[1, 2, 3, 9, 5, 100, 105, 1], 2), vec![(3, 9), (4, 5), (5, 100), (7, 1)]);
+    }
+
+    #[test]
+    fn preamble_boundary() {
+        // the preamble itself is never checked, the first number after it is
+        assert_eq!(invalid(&[7, 7, 7], 3), vec![]);
+        assert_eq!(invalid(&[7, 7, 7, 7], 3), vec![(3, 7)]);
+        assert_eq!(invalid(&[1, 2, 3], 0), vec![(0, 1), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn extreme_values() {
+        // a wrapping MAX + 1 would pass for MIN
+        assert_eq!(invalid(&[i64::MAX, i64::MAX, 1, i64::MIN], 2), vec![(2, 1), (3, i64::MIN)]);
+        assert_eq!(invalid(&[i64::MIN, i64::MAX, -1], 2), vec![]);
+        assert_eq!(contiguous_ranges(&[i64::MAX, i64::MAX, i64::MIN, i64::MIN], -1, 2), vec![1..3]);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut checked = validate("1 2 3 x\n9 4".as_bytes(), 2);
+        match checked.next() {
+            Some(Err(ReadError::InvalidNumber { line, text })) => assert_eq!((line, text.as_str()), (1, "x")),
+            other => panic!("unexpected {:?}", other)
         }
+        assert_eq!(checked.next().unwrap().unwrap(), Invalid { position: 3, value: 9 });
+        assert_eq!(checked.next().unwrap().unwrap(), Invalid { position: 4, value: 4 });
+        assert!(checked.next().is_none());
     }
 
-    let slice = &numbers[start..end];
-    println!("Solution: {}", slice.iter().min().unwrap() + slice.iter().max().unwrap())
+    #[test]
+    fn ranges_with_negative_numbers() {
+        assert_eq!(contiguous_ranges(&[3, -1, 4, -2, 5], 4, 1), vec![0..4, 2..3]);
+        assert_eq!(contiguous_ranges(&[3, -1, 4, -2, 5], 4, 2), vec![0..4]);
+        assert_eq!(contiguous_ranges(&[-3, 3, -3], 0, 2), vec![0..2, 1..3]);
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        assert_eq!(contiguous_ranges(&[1, 1, 1, 1], 2, 2), vec![0..2, 1..3, 2..4]);
+        assert_eq!(contiguous_ranges(&[0, 0], 0, 1), vec![0..1, 0..2, 1..2]);
+        assert_eq!(contiguous_ranges(&[2, 0, 0, 2], 2, 3), vec![0..3, 1..4]);
+    }
+
+    #[test]
+    fn no_matching_range() {
+        assert!(contiguous_ranges(&[1, 2, 3], 7, 1).is_empty());
+        assert!(contiguous_ranges(&[1, 2, 3], 3, 3).is_empty());
+        assert!(contiguous_ranges(&[], 0, 1).is_empty());
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut numbers = read_numbers("1 2\n3 x4\n5".as_bytes());
+        assert_eq!(numbers.by_ref().take(3).map(Result::unwrap).collect::<Vec<_>>(), vec![1, 2, 3]);
+        match numbers.next() {
+            Some(Err(ReadError::InvalidNumber { line, text })) => assert_eq!((line, text.as_str()), (2, "x4")),
+            other => panic!("unexpected {:?}", other)
+        }
+        assert_eq!(numbers.next().unwrap().unwrap(), 5);
+    }
 }
 
+#[cfg(test)]
+pub const EXAMPLE_INPUT: &str = "35
+20
+15
+25
+47
+40
+62
+55
+65
+95
+102
+117
+150
+182
+127
+219
+299
+277
+309
+576";
+
 pub const INPUT: &str = "48
 34
 42
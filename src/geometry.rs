@@ -0,0 +1,239 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+#[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Vector2D(pub i64, pub i64);
+
+impl Add for Vector2D {
+    type Output = Vector2D;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Vector2D(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl AddAssign for Vector2D {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs
+    }
+}
+
+impl Sub for Vector2D {
+    type Output = Vector2D;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vector2D(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl Neg for Vector2D {
+    type Output = Vector2D;
+
+    fn neg(self) -> Self::Output {
+        Vector2D(-self.0, -self.1)
+    }
+}
+
+impl Mul<i64> for Vector2D {
+    type Output = Vector2D;
+
+    fn mul(self, rhs: i64) -> Self::Output {
+        Vector2D(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+impl Vector2D {
+    pub fn dot(self, other: Self) -> i64 {
+        self.0 * other.0 + self.1 * other.1
+    }
+
+    // z component of the 3D cross product, positive if other is counterclockwise of self
+    pub fn cross(self, other: Self) -> i64 {
+        self.0 * other.1 - self.1 * other.0
+    }
+
+    pub fn manhattan(self) -> i64 {
+        self.0.abs() + self.1.abs()
+    }
+
+    pub fn chebyshev(self) -> i64 {
+        self.0.abs().max(self.1.abs())
+    }
+
+    pub fn euclidean(self) -> f64 {
+        self.to_f64().euclidean()
+    }
+
+    pub fn to_f64(self) -> Vector2F {
+        Vector2F(self.0 as f64, self.1 as f64)
+    }
+
+    // clockwise quarter turns, negative steps turn counterclockwise
+    pub fn rotate_steps(self, steps: i64) -> Self {
+        match steps.rem_euclid(4) {
+            0 => self,
+            1 => Vector2D(self.1, -self.0),
+            2 => Vector2D(-self.0, -self.1),
+            3 => Vector2D(-self.1, self.0),
+            _ => unreachable!()
+        }
+    }
+
+    // exact clockwise rotation, None unless degrees is a multiple of 90
+    pub fn rotate_degrees(self, degrees: i64) -> Option<Self> {
+        if degrees % 90 == 0 {
+            Some(self.rotate_steps(degrees / 90))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vector2F(pub f64, pub f64);
+
+impl Add for Vector2F {
+    type Output = Vector2F;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Vector2F(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl Sub for Vector2F {
+    type Output = Vector2F;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vector2F(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl Neg for Vector2F {
+    type Output = Vector2F;
+
+    fn neg(self) -> Self::Output {
+        Vector2F(-self.0, -self.1)
+    }
+}
+
+impl Mul<f64> for Vector2F {
+    type Output = Vector2F;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Vector2F(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+impl Vector2F {
+    pub fn dot(self, other: Self) -> f64 {
+        self.0 * other.0 + self.1 * other.1
+    }
+
+    pub fn cross(self, other: Self) -> f64 {
+        self.0 * other.1 - self.1 * other.0
+    }
+
+    pub fn manhattan(self) -> f64 {
+        self.0.abs() + self.1.abs()
+    }
+
+    pub fn chebyshev(self) -> f64 {
+        self.0.abs().max(self.1.abs())
+    }
+
+    pub fn euclidean(self) -> f64 {
+        self.0.hypot(self.1)
+    }
+
+    // clockwise, like Vector2D::rotate_steps
+    pub fn rotate_degrees(self, degrees: f64) -> Self {
+        let (sin, cos) = (-degrees.to_radians()).sin_cos();
+        Vector2F(self.0 * cos - self.1 * sin, self.0 * sin + self.1 * cos)
+    }
+
+    pub fn round(self) -> Vector2D {
+        Vector2D(self.0.round() as i64, self.1.round() as i64)
+    }
+}
+
+// smallest axis aligned box containing all given points, bounds are inclusive
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BoundingBox<const N: usize> {
+    pub min: [i64; N],
+    pub max: [i64; N],
+}
+
+impl<const N: usize> BoundingBox<N> {
+    pub fn around(point: [i64; N]) -> Self {
+        BoundingBox { min: point, max: point }
+    }
+
+    pub fn from_points<I>(points: I) -> Option<Self> where I: IntoIterator<Item=[i64; N]> {
+        let mut points = points.into_iter();
+        let mut bounds = Self::around(points.next()?);
+        points.for_each(|p| bounds.include(p));
+        Some(bounds)
+    }
+
+    pub fn include(&mut self, point: [i64; N]) {
+        for (i, coordinate) in point.iter().enumerate() {
+            self.min[i] = self.min[i].min(*coordinate);
+            self.max[i] = self.max[i].max(*coordinate);
+        }
+    }
+
+    // number of integer points along each axis
+    pub fn extent(&self) -> [i64; N] {
+        let mut extent = [0; N];
+        for (i, length) in extent.iter_mut().enumerate() {
+            *length = self.max[i] - self.min[i] + 1;
+        }
+        extent
+    }
+}
+
+impl From<Vector2D> for [i64; 2] {
+    fn from(Vector2D(x, y): Vector2D) -> Self {
+        [x, y]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::geometry::*;
+
+    #[test]
+    fn arithmetic_and_norms() {
+        let v = Vector2D(3, -4);
+        assert_eq!(v - Vector2D(1, 1), Vector2D(2, -5));
+        assert_eq!(-v, Vector2D(-3, 4));
+        assert_eq!(v.dot(Vector2D(2, 1)), 2);
+        assert_eq!(Vector2D(1, 0).cross(Vector2D(0, 1)), 1);
+        assert_eq!(v.manhattan(), 7);
+        assert_eq!(v.chebyshev(), 4);
+        assert_eq!(v.euclidean(), 5.0);
+    }
+
+    #[test]
+    fn rotation() {
+        let east = Vector2D(1, 0);
+        assert_eq!(east.rotate_steps(1), Vector2D(0, -1));
+        assert_eq!(east.rotate_steps(-1), Vector2D(0, 1));
+        assert_eq!(east.rotate_steps(-7), Vector2D(0, -1));
+        assert_eq!(east.rotate_degrees(270), Some(Vector2D(0, 1)));
+        assert_eq!(east.rotate_degrees(45), None);
+
+        for degrees in (-720..=720).step_by(90) {
+            assert_eq!(Vector2D(10, 4).to_f64().rotate_degrees(degrees as f64).round(), Vector2D(10, 4).rotate_degrees(degrees).unwrap());
+        }
+        let diagonal = east.to_f64().rotate_degrees(45.0);
+        assert!((diagonal.0 - 0.5f64.sqrt()).abs() < 1e-9 && (diagonal.1 + 0.5f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bounding_box() {
+        let bounds = BoundingBox::from_points(vec![[1, 5], [-2, 3], [0, 7]]).unwrap();
+        assert_eq!(bounds, BoundingBox { min: [-2, 3], max: [1, 7] });
+        assert_eq!(bounds.extent(), [4, 5]);
+        assert_eq!(BoundingBox::<4>::from_points(vec![]), None);
+    }
+}
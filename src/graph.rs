@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io;
+use std::io::Write;
+use std::ops::{Add, Mul};
+
+use num::One;
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum VisitState {
+    New,
+    Active,
+    Done,
+}
+
+#[derive(Debug, Clone)]
+pub struct Graph<L, W> {
+    ids: HashMap<L, NodeId>,
+    labels: Vec<L>,
+    forward: Vec<Vec<(NodeId, W)>>,
+    reverse: Vec<Vec<(NodeId, W)>>,
+}
+
+impl<L, W> Default for Graph<L, W> where L: Hash + Eq + Clone, W: Copy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L, W> Graph<L, W> where L: Hash + Eq + Clone, W: Copy {
+    pub fn new() -> Self {
+        Graph { ids: HashMap::new(), labels: Vec::new(), forward: Vec::new(), reverse: Vec::new() }
+    }
+
+    pub fn node(&mut self, label: L) -> NodeId {
+        if let Some(id) = self.ids.get(&label) {
+            return *id;
+        }
+        let id = NodeId(self.labels.len());
+        self.ids.insert(label.clone(), id);
+        self.labels.push(label);
+        self.forward.push(Vec::new());
+        self.reverse.push(Vec::new());
+        id
+    }
+
+    pub fn id(&self, label: &L) -> Option<NodeId> {
+        self.ids.get(label).copied()
+    }
+
+    pub fn label(&self, id: NodeId) -> &L {
+        &self.labels[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item=NodeId> {
+        (0..self.labels.len()).map(NodeId)
+    }
+
+    pub fn add_edge(&mut self, from: NodeId, to: NodeId, weight: W) {
+        self.forward[from.0].push((to, weight));
+        self.reverse[to.0].push((from, weight));
+    }
+
+    pub fn successors(&self, id: NodeId) -> &[(NodeId, W)] {
+        &self.forward[id.0]
+    }
+
+    // Kahn's algorithm, None if the graph has a cycle
+    pub fn topological_order(&self) -> Option<Vec<NodeId>> {
+        let mut in_degree = self.reverse.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut ready = self.nodes().filter(|n| in_degree[n.0] == 0).collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(self.len());
+
+        while let Some(next) = ready.pop_front() {
+            order.push(next);
+            for (child, _) in &self.forward[next.0] {
+                in_degree[child.0] -= 1;
+                if in_degree[child.0] == 0 {
+                    ready.push_back(*child)
+                }
+            }
+        }
+
+        if order.len() == self.len() {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    // every node that can be reached from start, start itself excluded unless it lies on a cycle
+    pub fn descendants(&self, start: NodeId) -> HashSet<NodeId> {
+        Self::reachable(&self.forward, start)
+    }
+
+    // every node from which start can be reached
+    pub fn ancestors(&self, start: NodeId) -> HashSet<NodeId> {
+        Self::reachable(&self.reverse, start)
+    }
+
+    // one cycle per back edge found by a depth first search, each starting and ending in the same node.
+    // Iterative, like path_sum
+    pub fn cycles(&self) -> Vec<Vec<NodeId>> {
+        let mut state = vec![VisitState::New; self.len()];
+        let mut found = Vec::new();
+        // nodes on the current path, with the next child to look at
+        let mut stack = Vec::new();
+        for root in self.nodes() {
+            if state[root.0] != VisitState::New {
+                continue
+            }
+            state[root.0] = VisitState::Active;
+            stack.push((root, 0));
+            while let Some(&(current, index)) = stack.last() {
+                match self.forward[current.0].get(index) {
+                    Some((child, _)) => {
+                        stack.last_mut().unwrap().1 += 1;
+                        match state[child.0] {
+                            VisitState::New => {
+                                state[child.0] = VisitState::Active;
+                                stack.push((*child, 0))
+                            }
+                            VisitState::Active => {
+                                let start = stack.iter().rposition(|(n, _)| n == child).unwrap();
+                                let mut cycle = stack[start..].iter().map(|(n, _)| *n).collect::<Vec<_>>();
+                                cycle.push(*child);
+                                found.push(cycle)
+                            }
+                            VisitState::Done => (),
+                        }
+                    }
+                    None => {
+                        state[current.0] = VisitState::Done;
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    fn reachable(adjacency: &[Vec<(NodeId, W)>], start: NodeId) -> HashSet<NodeId> {
+        let mut seen = HashSet::new();
+        let mut open = vec![start];
+        while let Some(next) = open.pop() {
+            for (neighbour, _) in &adjacency[next.0] {
+                if seen.insert(*neighbour) {
+                    open.push(*neighbour)
+                }
+            }
+        }
+        seen
+    }
+}
+
+impl<L, W> Graph<L, W> where L: Hash + Eq + Clone + Display, W: Copy + Display {
+    // graphviz output of all nodes accepted by include, and the edges between them
+    pub fn write_dot<O, F>(&self, out: &mut O, include: F) -> io::Result<()> where O: Write, F: Fn(NodeId) -> bool {
+        fn quoted<T: Display>(value: &T) -> String {
+            format!("\"{}\"", value.to_string().replace('"', "\\\""))
+        }
+
+        writeln!(out, "digraph {{")?;
+        for node in self.nodes().filter(|n| include(*n)) {
+            writeln!(out, "    {};", quoted(self.label(node)))?;
+        }
+        for node in self.nodes().filter(|n| include(*n)) {
+            for (child, weight) in self.successors(node).iter().filter(|(c, _)| include(*c)) {
+                writeln!(out, "    {} -> {} [label={}];", quoted(self.label(node)), quoted(self.label(*child)), quoted(weight))?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+// a cycle met while evaluating the graph, starting and ending in the same node
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CycleError(pub Vec<NodeId>);
+
+impl<L, W> Graph<L, W> where L: Hash + Eq + Clone, W: Copy {
+    // 1 + the weighted sum over all children - i.e. the node itself plus everything it expands into.
+    // The sum may use a wider type than the weights. Every node is evaluated once, results are kept in memo.
+    // Iterative, so deep graphs don't exhaust the stack
+    pub fn path_sum<T>(&self, node: NodeId, memo: &mut HashMap<NodeId, T>) -> Result<T, CycleError>
+        where T: Clone + One + Add<Output=T> + Mul<W, Output=T> {
+        if let Some(known) = memo.get(&node) {
+            return Ok(known.clone());
+        }
+
+        // nodes on the current path, with the next child to look at
+        let mut stack = vec![(node, 0)];
+        let mut active = HashSet::new();
+        active.insert(node);
+        while let Some(&(current, index)) = stack.last() {
+            match self.forward[current.0].get(index) {
+                Some((child, _)) => {
+                    stack.last_mut().unwrap().1 += 1;
+                    if memo.contains_key(child) {
+                        continue
+                    }
+                    if !active.insert(*child) {
+                        let start = stack.iter().position(|(n, _)| n == child).unwrap();
+                        let mut cycle = stack[start..].iter().map(|(n, _)| *n).collect::<Vec<_>>();
+                        cycle.push(*child);
+                        return Err(CycleError(cycle));
+                    }
+                    stack.push((*child, 0))
+                }
+                None => {
+                    let mut sum = T::one();
+                    for (child, weight) in &self.forward[current.0] {
+                        sum = sum + memo[child].clone() * *weight;
+                    }
+                    memo.insert(current, sum);
+                    active.remove(&current);
+                    stack.pop();
+                }
+            }
+        }
+
+        Ok(memo[&node].clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::graph::*;
+
+    fn diamond() -> Graph<&'static str, u32> {
+        let mut graph = Graph::new();
+        let top = graph.node("top");
+        let left = graph.node("left");
+        let right = graph.node("right");
+        let bottom = graph.node("bottom");
+        graph.add_edge(top, left, 2);
+        graph.add_edge(top, right, 3);
+        graph.add_edge(left, bottom, 4);
+        graph.add_edge(right, bottom, 1);
+        graph
+    }
+
+    #[test]
+    fn interns_labels() {
+        let mut graph = diamond();
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.node("left"), graph.id(&"left").unwrap());
+        assert_eq!(graph.len(), 4);
+        assert_eq!(*graph.label(graph.id(&"bottom").unwrap()), "bottom");
+        assert_eq!(graph.id(&"missing"), None);
+    }
+
+    #[test]
+    fn reachability() {
+        let graph = diamond();
+        let ids = |names: &[&'static str]| names.iter().map(|n| graph.id(n).unwrap()).collect::<HashSet<_>>();
+        assert_eq!(graph.ancestors(graph.id(&"bottom").unwrap()), ids(&["top", "left", "right"]));
+        assert_eq!(graph.descendants(graph.id(&"top").unwrap()), ids(&["left", "right", "bottom"]));
+        assert!(graph.descendants(graph.id(&"bottom").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn topological_order() {
+        let mut graph = diamond();
+        let order = graph.topological_order().unwrap();
+        let position = |name| order.iter().position(|n| *n == graph.id(&name).unwrap()).unwrap();
+        assert!(position("top") < position("left"));
+        assert!(position("left") < position("bottom"));
+        assert!(position("right") < position("bottom"));
+
+        let bottom = graph.id(&"bottom").unwrap();
+        let top = graph.id(&"top").unwrap();
+        graph.add_edge(bottom, top, 1);
+        assert_eq!(graph.topological_order(), None);
+    }
+
+    #[test]
+    fn cycles() {
+        let mut graph = diamond();
+        assert!(graph.cycles().is_empty());
+
+        let top = graph.id(&"top").unwrap();
+        let left = graph.id(&"left").unwrap();
+        let bottom = graph.id(&"bottom").unwrap();
+        graph.add_edge(bottom, top, 1);
+        graph.add_edge(left, left, 1);
+        let cycles = graph.cycles();
+        assert_eq!(cycles.len(), 2);
+        assert!(cycles.contains(&vec![left, left]));
+        assert!(cycles.contains(&vec![top, left, bottom, top]));
+
+        // long chains must not overflow the stack
+        let mut chain = Graph::<usize, u64>::new();
+        for i in 1..200_000 {
+            let (from, to) = (chain.node(i - 1), chain.node(i));
+            chain.add_edge(from, to, 1);
+        }
+        assert!(chain.cycles().is_empty());
+        chain.add_edge(NodeId(199_999), NodeId(100_000), 1);
+        assert_eq!(chain.cycles().iter().map(Vec::len).collect::<Vec<_>>(), vec![100_001]);
+    }
+
+    #[test]
+    fn path_sum() {
+        let graph = diamond();
+        let mut memo = HashMap::new();
+        // 1 + 2 * (1 + 4) + 3 * (1 + 1)
+        assert_eq!(graph.path_sum(graph.id(&"top").unwrap(), &mut memo), Ok(17));
+        assert_eq!(memo[&graph.id(&"left").unwrap()], 5);
+        assert_eq!(memo.len(), 4);
+    }
+
+    #[test]
+    fn path_sum_cycles() {
+        let mut graph = diamond();
+        let top = graph.id(&"top").unwrap();
+        let right = graph.id(&"right").unwrap();
+        let bottom = graph.id(&"bottom").unwrap();
+        graph.add_edge(bottom, right, 1);
+        assert_eq!(graph.path_sum::<u32>(top, &mut HashMap::new()), Err(CycleError(vec![bottom, right, bottom])));
+
+        // long chains must not overflow the stack
+        let mut chain = Graph::<usize, u64>::new();
+        assert_eq!(chain.len(), 0);
+        for i in 1..200_000 {
+            let (from, to) = (chain.node(i - 1), chain.node(i));
+            chain.add_edge(from, to, 1);
+        }
+        assert_eq!(chain.path_sum(NodeId(0), &mut HashMap::new()), Ok(200_000));
+    }
+}
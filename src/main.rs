@@ -2,7 +2,8 @@
 #![feature(linked_list_cursors)]
 #![feature(duration_consts_2)]
 #![feature(fixed_size_array)]
-#![feature(box_syntax)]
+// the days keep their tests ahead of the puzzle input
+#![allow(clippy::items_after_test_module)]
 /*
 mod day1 {
     pub fn solve_basic(input: &[&str]) {
@@ -277,29 +278,49 @@ mod day4 {
 
 mod day5;
 mod day6;
-mod day7;
 
 */
 //mod debug_vm;
+mod cli;
+mod graph;
+mod automaton;
+mod geometry;
+mod crt;
+mod modular;
+mod day7;
 // mod day8;
-// mod day9;
-// mod day10;
-// mod day11;
-// mod day12;
-// mod day13;
-// mod day14;
-// mod day15;
+mod day9;
+mod day10;
+mod day11;
+mod day12;
+mod day13;
+mod day14;
+mod day15;
 // mod day16;
-// mod day17;
+mod day17;
 // mod day18;
 // mod day19;
 mod day20;
 // mod day21;
 // mod day22;
 // mod day23;
-// mod day24;
-// mod day25;
+mod day24;
+mod day25;
 
 fn main() {
-    day20::solve()
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("day7") => day7::query(&args[1..]),
+        Some("day9") => day9::solve(day9::INPUT, 25),
+        Some("day10") => day10::query(&args[1..]),
+        Some("day11") => day11::query(&args[1..]),
+        Some("day12") => day12::query(&args[1..]),
+        Some("day13") => day13::query(&args[1..]),
+        Some("day14") => day14::query(&args[1..]),
+        Some("day15") => day15::query(&args[1..]),
+        Some("day17") => day17::solve(day17::INPUT),
+        Some("day24") => day24::solve(),
+        Some("day25") => day25::solve(),
+        _ => day20::solve()
+    }
 }
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::Integer;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ModularError {
+    InvalidModulus(i64),
+    MismatchedModuli(i64, i64),
+    NotInvertible { value: i64, modulus: i64 },
+}
+
+impl Display for ModularError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModularError::InvalidModulus(m) => write!(f, "{} is not a valid modulus", m),
+            ModularError::MismatchedModuli(a, b) => write!(f, "cannot combine values modulo {} and {}", a, b),
+            ModularError::NotInvertible { value, modulus } => write!(f, "{} has no inverse modulo {}", value, modulus),
+        }
+    }
+}
+
+fn reduce(value: i128, modulus: i64) -> i64 {
+    value.rem_euclid(modulus as i128) as i64
+}
+
+// square and multiply, panics on a non-positive modulus
+pub fn pow_mod(base: i64, mut exponent: u64, modulus: i64) -> i64 {
+    assert!(modulus > 0, "{} is not a valid modulus", modulus);
+    let mut result = reduce(1, modulus);
+    let mut square = reduce(base as i128, modulus);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = reduce(result as i128 * square as i128, modulus)
+        }
+        square = reduce(square as i128 * square as i128, modulus);
+        exponent >>= 1;
+    }
+    result
+}
+
+pub fn inverse_mod(value: i64, modulus: i64) -> Option<i64> {
+    let gcd = value.extended_gcd(&modulus);
+    if gcd.gcd == 1 {
+        Some(reduce(gcd.x as i128, modulus))
+    } else {
+        None
+    }
+}
+
+// value with a modulus chosen at runtime. Operators panic when mixing moduli, the checked_ variants don't
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ModuleValue {
+    value_in_modulus: i64,
+    modulus: i64,
+}
+
+macro_rules! checked_arithmetics {
+    ($tpe:ident, $name:ident, $checked:ident, $op:tt) => {
+        impl ModuleValue {
+            pub fn $checked(self, rhs: ModuleValue) -> Result<ModuleValue, ModularError> {
+                self.verify(rhs.modulus)?;
+                Ok(ModuleValue::new(self.value_in_modulus as i128 $op rhs.value_in_modulus as i128, self.modulus))
+            }
+        }
+
+        impl $tpe for ModuleValue {
+            type Output = ModuleValue;
+
+            fn $name(self, rhs: ModuleValue) -> ModuleValue {
+                self.$checked(rhs).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+checked_arithmetics!(Add, add, checked_add, +);
+checked_arithmetics!(Sub, sub, checked_sub, -);
+checked_arithmetics!(Mul, mul, checked_mul, *);
+
+impl ModuleValue {
+    // panics on a non-positive modulus, see try_new
+    pub fn new<V: Into<i128>>(value: V, modulus: i64) -> Self {
+        Self::try_new(value, modulus).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new<V: Into<i128>>(value: V, modulus: i64) -> Result<Self, ModularError> {
+        if modulus <= 0 {
+            return Err(ModularError::InvalidModulus(modulus));
+        }
+        Ok(ModuleValue { value_in_modulus: reduce(value.into(), modulus), modulus })
+    }
+
+    pub fn value(self) -> i64 {
+        self.value_in_modulus
+    }
+
+    pub fn inverse(self) -> Result<Self, ModularError> {
+        inverse_mod(self.value_in_modulus, self.modulus)
+            .map(|value_in_modulus| ModuleValue { value_in_modulus, modulus: self.modulus })
+            .ok_or(ModularError::NotInvertible { value: self.value_in_modulus, modulus: self.modulus })
+    }
+
+    pub fn checked_div(self, rhs: ModuleValue) -> Result<Self, ModularError> {
+        self.verify(rhs.modulus)?;
+        Ok(self * rhs.inverse()?)
+    }
+
+    fn verify(self, m: i64) -> Result<(), ModularError> {
+        if m == self.modulus {
+            Ok(())
+        } else {
+            Err(ModularError::MismatchedModuli(self.modulus, m))
+        }
+    }
+}
+
+impl Div for ModuleValue {
+    type Output = ModuleValue;
+
+    fn div(self, rhs: ModuleValue) -> Self::Output {
+        self.checked_div(rhs).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Neg for ModuleValue {
+    type Output = ModuleValue;
+
+    fn neg(self) -> Self::Output {
+        ModuleValue::new(-self.value_in_modulus, self.modulus)
+    }
+}
+
+impl Display for ModuleValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (mod {})", self.value_in_modulus, self.modulus)
+    }
+}
+
+// value with the modulus fixed at compile time, so moduli can never be mixed up
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Modular<const M: i64>(i64);
+
+impl<const M: i64> Modular<M> {
+    // evaluated once per modulus in use, so Modular<0> fails to compile
+    const VALID: () = assert!(M > 0, "the modulus must be positive");
+
+    pub fn new<V: Into<i128>>(value: V) -> Self {
+        let () = Self::VALID;
+        Modular(reduce(value.into(), M))
+    }
+
+    pub fn value(self) -> i64 {
+        self.0
+    }
+
+    pub fn pow(self, exponent: u64) -> Self {
+        Modular(pow_mod(self.0, exponent, M))
+    }
+
+    pub fn inverse(self) -> Option<Self> {
+        inverse_mod(self.0, M).map(Modular)
+    }
+
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        rhs.inverse().map(|inverse| self * inverse)
+    }
+}
+
+impl<const M: i64> Default for Modular<M> {
+    fn default() -> Self {
+        Modular::new(0)
+    }
+}
+
+macro_rules! const_arithmetics {
+    ($tpe:ident, $name:ident, $op:tt) => {
+        impl<const M: i64> $tpe for Modular<M> {
+            type Output = Modular<M>;
+
+            fn $name(self, rhs: Modular<M>) -> Modular<M> {
+                Modular::new(self.0 as i128 $op rhs.0 as i128)
+            }
+        }
+    };
+}
+
+const_arithmetics!(Add, add, +);
+const_arithmetics!(Sub, sub, -);
+const_arithmetics!(Mul, mul, *);
+
+impl<const M: i64> Div for Modular<M> {
+    type Output = Modular<M>;
+
+    fn div(self, rhs: Modular<M>) -> Self::Output {
+        self.checked_div(rhs).unwrap_or_else(|| panic!("{} has no inverse modulo {}", rhs.0, M))
+    }
+}
+
+impl<const M: i64> Neg for Modular<M> {
+    type Output = Modular<M>;
+
+    fn neg(self) -> Self::Output {
+        Modular::new(-self.0)
+    }
+}
+
+impl<const M: i64> Display for Modular<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (mod {})", self.0, M)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::modular::*;
+
+    #[test]
+    fn runtime_modulus() {
+        let a = ModuleValue::new(-3, 7);
+        let b = ModuleValue::new(5, 7);
+        assert_eq!(a.value(), 4);
+        assert_eq!((a + b).value(), 2);
+        assert_eq!((a - b).value(), 6);
+        assert_eq!((a * b).value(), 6);
+        assert_eq!((-b).value(), 2);
+        assert_eq!((a / b * b), a);
+        assert_eq!(pow_mod(-3, 3, 7), 1);
+        assert_eq!(pow_mod(12, 2, 7), 4);
+        assert_eq!(pow_mod(5, 0, 1), 0);
+
+        assert_eq!(a.checked_add(ModuleValue::new(1, 5)), Err(ModularError::MismatchedModuli(7, 5)));
+        assert_eq!(ModuleValue::new(4, 6).inverse(), Err(ModularError::NotInvertible { value: 4, modulus: 6 }));
+        assert_eq!(ModuleValue::try_new(1, 0), Err(ModularError::InvalidModulus(0)));
+    }
+
+    #[test]
+    fn compile_time_modulus() {
+        type Key = Modular<20201227>;
+        assert_eq!(Key::new(7).pow(8).value(), 5764801);
+        assert_eq!(Key::new(17807724).pow(8).value(), 14897079);
+        assert_eq!(Key::new(3) / Key::new(3), Key::new(1));
+        assert_eq!(Modular::<10>::new(5).inverse(), None);
+
+        // products must not overflow for large moduli
+        type Big = Modular<{ i64::MAX }>;
+        assert_eq!((Big::new(-1) * Big::new(-1)).value(), 1);
+    }
+}